@@ -1,77 +1,157 @@
 use failure::bail;
 
+use super::expr::{Scope, Values};
 use super::*;
+use crate::dbg::Kind;
+
+/// Memory as loaded from the file image
+impl examine::Memory for Env<Binary> {
+    fn object(&self) -> &Binary {
+        &self.inner
+    }
+
+    fn read_memory(&mut self, addr: Address, size: usize) -> Result<Vec<u8>> {
+        self.inner.read(addr, size)
+    }
+}
+
+/// Expressions see the file image, but no registers
+impl Scope for Env<Binary> {
+    fn symbols(&self) -> Option<&Binary> {
+        Some(&self.inner)
+    }
+
+    fn values(&mut self) -> &mut Values {
+        &mut self.values
+    }
+
+    fn load(&mut self, addr: Address, size: usize) -> Result<Vec<u8>> {
+        self.inner.read(addr, size)
+    }
+}
 
 /// Handle "pure memory", static analysis
 impl Env<Binary> {
     pub fn handle_command(&mut self, cmd: Cmd) -> Result<Option<Event>> {
         match cmd {
-            Cmd::Break { loc } => self.break_command(loc),
-            Cmd::Delete { args } => self.delete_command(args),
-            Cmd::Disable { args } => self.disable_command(args),
-            Cmd::Enable { args } => self.enable_command(args),
+            Cmd::Break { loc, cond } => self.break_command(loc, cond, false),
+            Cmd::Tbreak { loc, cond } => self.break_command(loc, cond, true),
+            Cmd::Hbreak { loc, cond } => {
+                self.add_hw_breakpoint(&loc, cond).map(|_| None)
+            }
+            Cmd::Condition { num, cond } => self.condition_command(num, cond),
+            Cmd::Commands { num } => self.commands_command(num),
+            Cmd::Handle { args } => self.handle_signals_command(args),
+            Cmd::Info { cmd } => self.info_without_process(cmd),
+            Cmd::Ignore { num, count } => self.ignore_command(num, count),
+            Cmd::Watch { expr } => self.watch_command(expr, Kind::Watch),
+            Cmd::Rwatch { expr } => self.watch_command(expr, Kind::ReadWatch),
+            Cmd::Awatch { expr } => self.watch_command(expr, Kind::AccessWatch),
+            Cmd::Delete { args } => self.delete_breakpoints(args),
+            Cmd::Disable { args } => self.enable_breakpoints(args, false),
+            Cmd::Enable { args } => self.enable_breakpoints(args, true),
             Cmd::Examine { fmt, addr } => self.examine_command(fmt, addr),
             Cmd::File { path } => self.set_file(path),
-            Cmd::Repeat => self.repeat_command(),
+            Cmd::Attach { pid } => self.attach_process(pid),
+            Cmd::Thread { num } => self.thread_without_process(num),
+            Cmd::Repeat => Ok(None),
             Cmd::Run { args } => self.run_command(args),
+            Cmd::Start { args } => self.start_command(args),
+            Cmd::Starti { args } => self.starti_command(args),
             Cmd::Set { expr, cmd } => self.handle_set_command(expr, cmd),
-            Cmd::Info { .. } | Cmd::Continue { .. } | Cmd::Stepi { .. } => {
+            Cmd::Print { fmt, expr } => self.print_command(fmt, expr),
+            Cmd::Disassemble { span } => self.disassemble_command(span),
+            Cmd::Continue { .. }
+            | Cmd::Stepi { .. }
+            | Cmd::Detach
+            | Cmd::Kill => {
                 bail!("The program is not being run.")
             }
         }
     }
 
-    fn break_command(&mut self, loc: usize) -> Result<Option<Event>> {
-        self.add_breakpoint(loc);
+    fn break_command(
+        &mut self,
+        loc: Location,
+        cond: Option<String>,
+        temporary: bool,
+    ) -> Result<Option<Event>> {
+        self.set_breakpoint(loc, cond, temporary)?;
         Ok(None)
     }
 
-    fn delete_command(&mut self, args: Vec<usize>) -> Result<Option<Event>> {
-        for num in args {
-            if self.breakpoints.remove(&num).is_none() {
-                println!("No breakpoint number {}.", num);
-            }
-        }
-        Ok(None)
+    /// Add a breakpoint at a location, returning its number
+    fn set_breakpoint(
+        &mut self,
+        loc: Location,
+        cond: Option<String>,
+        temporary: bool,
+    ) -> Result<usize> {
+        let mut bp = env::location_breakpoint(self, &loc)?;
+        bp.condition = env::check_condition(cond)?;
+        bp.temporary = temporary;
+        let num = self.add_location_breakpoint(bp, &loc);
+        env::print_breakpoint(&self.inner, num, &self.breakpoints[&num]);
+        Ok(num)
     }
 
-    fn disable_command(&mut self, args: Vec<usize>) -> Result<Option<Event>> {
-        for num in args {
-            if let Some(bp) = self.breakpoints.get_mut(&num) {
-                bp.enabled = false;
-            }
-        }
+    fn watch_command(
+        &mut self,
+        expr: String,
+        kind: Kind,
+    ) -> Result<Option<Event>> {
+        self.add_watchpoint(expr, kind)?;
         Ok(None)
     }
 
-    fn enable_command(&mut self, args: Vec<usize>) -> Result<Option<Event>> {
-        for num in args {
-            if let Some(bp) = self.breakpoints.get_mut(&num) {
-                bp.enabled = true;
+    fn run_command(&mut self, args: Vec<String>) -> Result<Option<Event>> {
+        Ok(Some(Event::Run(self.start_process(args)?)))
+    }
+
+    /// Run to a temporary breakpoint at main, not left behind if the
+    /// program can't be started
+    fn start_command(&mut self, args: Vec<String>) -> Result<Option<Event>> {
+        let num = self.set_breakpoint(env::main_location(), None, true)?;
+        match self.start_process(args) {
+            Ok(dbg) => Ok(Some(Event::Start(dbg))),
+            Err(e) => {
+                self.remove_breakpoint(num);
+                Err(e)
             }
         }
-        Ok(None)
     }
 
-    fn examine_command(
-        &mut self,
-        fmt: Option<Fmt>,
-        addr: Option<usize>,
-    ) -> Result<Option<Event>> {
-        dbg!((fmt, addr));
-        Ok(None)
+    /// Run, reporting the stop at the program's first instruction
+    fn starti_command(&mut self, args: Vec<String>) -> Result<Option<Event>> {
+        let mut dbg = self.start_process(args)?;
+        let pc = dbg.pc()?;
+        println!();
+        println!("Program stopped.");
+        println!("{}", env::frame_description(dbg.binary(), pc));
+        Ok(Some(Event::Run(dbg)))
     }
 
-    fn repeat_command(&mut self) -> Result<Option<Event>> {
-        Ok(None)
+    /// Attach to a running process of the program, with breakpoints inserted
+    pub fn attach(&mut self, pid: Pid) -> Result<Debugger> {
+        let mut dbg = Debugger::new(Binary::new(&self.path)?);
+        dbg.attach(pid)?;
+        self.relocate_breakpoints(self.inner.bias, dbg.binary().bias);
+        env::arm_breakpoints(&mut self.breakpoints, &mut dbg)?;
+        let pc = dbg.pc()?;
+        println!("{}", env::frame_description(dbg.binary(), pc));
+        Ok(dbg)
     }
 
-    fn run_command(&mut self, args: Vec<String>) -> Result<Option<Event>> {
+    /// Start a process stopped at its first instruction, with breakpoints
+    /// inserted
+    fn start_process(&mut self, args: Vec<String>) -> Result<Debugger> {
         if args.len() > 0 {
             self.set_args(args)?;
         }
-        let mut dbg = Debugger::new(&self.path)?;
-        dbg.run(self.args());
-        Ok(Some(Event::Run(dbg)))
+        let mut dbg = Debugger::new(Binary::new(&self.path)?);
+        dbg.run(self.args(), self.disable_randomization())?;
+        self.relocate_breakpoints(self.inner.bias, dbg.binary().bias);
+        env::arm_breakpoints(&mut self.breakpoints, &mut dbg)?;
+        Ok(dbg)
     }
 }
@@ -1,73 +1,483 @@
-use failure::bail;
-
 use std::path::PathBuf;
 
+use failure::bail;
+
+use super::examine::Memory;
+use super::expr::{self, Scope, Value, Values};
+use super::signal;
 use super::*;
+use crate::dbg::{hw::Slot, regs, FpRegisters, Kind, Registers, Signal};
+
+impl examine::Memory for Env<Debugger> {
+    fn object(&self) -> &Binary {
+        self.binary()
+    }
+
+    fn read_memory(&mut self, addr: Address, size: usize) -> Result<Vec<u8>> {
+        Ok(self.inner.read(addr, size)?)
+    }
+
+    fn current_pc(&mut self) -> Option<Address> {
+        self.inner.pc().ok()
+    }
+}
+
+/// Expressions see the live process
+impl Scope for Env<Debugger> {
+    fn symbols(&self) -> Option<&Binary> {
+        Some(self.binary())
+    }
+
+    fn values(&mut self) -> &mut Values {
+        &mut self.values
+    }
+
+    fn load(&mut self, addr: Address, size: usize) -> Result<Vec<u8>> {
+        self.read_memory(addr, size)
+    }
+
+    fn store(&mut self, addr: Address, data: &[u8]) -> Result<()> {
+        Ok(self.inner.write(addr, data)?)
+    }
+
+    fn register(&mut self, name: &str) -> Result<u64> {
+        let regs = self.inner.registers()?;
+        match regs.get(name) {
+            Some(value) => Ok(value),
+            None => bail!("Invalid register `{}'", name),
+        }
+    }
+
+    fn set_register(&mut self, name: &str, value: u64) -> Result<()> {
+        let mut regs = self.inner.registers()?;
+        if !regs.set(name, value) {
+            bail!("Invalid register `{}'", name);
+        }
+        Ok(self.inner.set_registers(&regs)?)
+    }
+}
 
 impl Env<Debugger> {
     pub fn handle_command(&mut self, cmd: Cmd) -> Result<Option<Event>> {
         match cmd {
-            Cmd::Break { loc } => self.break_command(loc),
+            Cmd::Break { loc, cond } => self.break_command(loc, cond, false),
+            Cmd::Tbreak { loc, cond } => self.break_command(loc, cond, true),
+            Cmd::Hbreak { loc, cond } => self.hbreak_command(loc, cond),
+            Cmd::Condition { num, cond } => self.condition_command(num, cond),
+            Cmd::Commands { num } => self.commands_command(num),
+            Cmd::Handle { args } => self.handle_signals_command(args),
+            Cmd::Ignore { num, count } => self.ignore_command(num, count),
+            Cmd::Watch { expr } => self.watch_command(expr, Kind::Watch),
+            Cmd::Rwatch { expr } => self.watch_command(expr, Kind::ReadWatch),
+            Cmd::Awatch { expr } => self.watch_command(expr, Kind::AccessWatch),
             Cmd::Continue { n } => self.continue_command(n),
             Cmd::Stepi { n } => self.stepi_command(n),
             Cmd::Delete { args } => self.delete_command(args),
             Cmd::Disable { args } => self.disable_command(args),
             Cmd::Enable { args } => self.enable_command(args),
+            Cmd::Disassemble { span } => self.disassemble_command(span),
             Cmd::Examine { fmt, addr } => self.examine_command(fmt, addr),
             Cmd::File { path } => self.file_command(path),
-            Cmd::Repeat => self.repeat_command(),
+            Cmd::Attach { pid } => self.attach_command(pid),
+            Cmd::Detach => self.detach_command(),
+            Cmd::Kill => self.kill_command(),
+            Cmd::Thread { num } => self.thread_command(num),
+            Cmd::Repeat => Ok(None),
             Cmd::Run { args } => self.run_command(args),
+            Cmd::Start { args } => self.start_command(args),
+            Cmd::Starti { args } => self.starti_command(args),
             Cmd::Set { expr, cmd } => self.handle_set_command(expr, cmd),
+            Cmd::Print { fmt, expr } => self.print_command(fmt, expr),
             Cmd::Info { cmd } => self.info_command(cmd),
         }
     }
 
-    fn break_command(&mut self, addr: usize) -> Result<Option<Event>> {
-        let num = self.add_breakpoint(addr);
-        self.breakpoints
-            .get_mut(&num)
-            .unwrap()
-            .enable(&mut self.inner)?;
+    fn break_command(
+        &mut self,
+        loc: Location,
+        cond: Option<String>,
+        temporary: bool,
+    ) -> Result<Option<Event>> {
+        let mut bp = env::location_breakpoint(self, &loc)?;
+        bp.condition = env::check_condition(cond)?;
+        bp.temporary = temporary;
+        let num = self.add_location_breakpoint(bp, &loc);
+        env::print_breakpoint(self.binary(), num, &self.breakpoints[&num]);
+        self.insert_breakpoint(num)
+    }
+
+    fn hbreak_command(
+        &mut self,
+        loc: Location,
+        cond: Option<String>,
+    ) -> Result<Option<Event>> {
+        let num = self.add_hw_breakpoint(&loc, cond)?;
+        self.insert_breakpoint(num)
+    }
+
+    fn watch_command(
+        &mut self,
+        expr: String,
+        kind: Kind,
+    ) -> Result<Option<Event>> {
+        let num = self.add_watchpoint(expr, kind)?;
+        self.insert_breakpoint(num)
+    }
+
+    /// Insert a new breakpoint into the process, deleting it again if it
+    /// can't be, e.g. when the debug registers are all in use
+    fn insert_breakpoint(&mut self, num: usize) -> Result<Option<Event>> {
+        let bp = self.breakpoints.get_mut(&num).expect("new breakpoint");
+        if let Err(e) = bp.enable(&mut self.inner) {
+            self.remove_breakpoint(num);
+            return Err(e.into());
+        }
         Ok(None)
     }
 
     fn continue_command(&mut self, n: usize) -> Result<Option<Event>> {
         println!("Continuing.");
+        self.resume(n)
+    }
+
+    /// Resume the process until it stops at a breakpoint n times, or exits
+    pub fn resume(&mut self, n: usize) -> Result<Option<Event>> {
         for _ in 0..n {
-            // Step over breakpoints on this address
-            let pc = self.inner.pc()?;
+            // Software watchpoints check every instruction, so single step
+            // instead of continuing
+            let stepping = self.software_watching();
+            loop {
+                let (_, resumed) = self.inner.thread()?;
+                // Continue execution, stepping over a breakpoint on this address
+                match self.resume_process(stepping)? {
+                    event @ DebugEvent::Exited(..)
+                    | event @ DebugEvent::Terminated(..)
+                    | event @ DebugEvent::Detached(..)
+                    | event @ DebugEvent::Killed(..) => {
+                        return Ok(Some(Event::Process(event)));
+                    }
+                    DebugEvent::Stopped => {
+                        if self.report_stop(stepping, resumed)? {
+                            break;
+                        }
+                    }
+                    DebugEvent::Received(signal) => {
+                        if self.report_signal(signal, resumed)? {
+                            return Ok(None);
+                        }
+                    }
+                    // Followed in resume_process
+                    DebugEvent::Forked(..)
+                    | DebugEvent::Exec
+                    | DebugEvent::VforkDone => (),
+                }
+            }
+        }
+        Ok(None)
+    }
 
-            for (_, bp) in self.breakpoints.iter_mut() {
-                if bp.addr == pc {
-                    bp.disable(&mut self.inner)?;
-                    self.inner.step()?;
-                    bp.enable(&mut self.inner)?;
+    /// Continue or single step the process, following it through forks and
+    /// the programs it executes
+    fn resume_process(&mut self, step: bool) -> Result<DebugEvent> {
+        loop {
+            let event = match step {
+                true => self.inner.step()?,
+                false => self.inner.cont()?,
+            };
+            match event {
+                DebugEvent::Forked(child, vfork) => {
+                    self.follow_fork(child, vfork)?
                 }
+                DebugEvent::Exec => self.follow_exec()?,
+                event => return Ok(event),
             }
-            // Continue execution
-            match self.inner.cont()? {
-                event @ DebugEvent::Exited(..) => {
-                    return Ok(Some(Event::Process(event)));
+        }
+    }
+
+    /// Debug the parent or the child after a fork, as set with `set
+    /// follow-fork-mode`, detaching the other or holding it stopped as set
+    /// with `set detach-on-fork`
+    fn follow_fork(&mut self, child: Pid, vfork: bool) -> Result<()> {
+        let parent = self.inner.proc()?.pid();
+        let follow_child = self.follow_fork_child();
+        let detach = self.detach_on_fork();
+        let call = match vfork {
+            true => "vfork",
+            false => "fork",
+        };
+        if follow_child {
+            println!(
+                "[Attaching after process {} {} to child process {}]",
+                parent, call, child
+            );
+        }
+        let (which, other) = match follow_child {
+            true => ("parent", parent),
+            false => ("child", child),
+        };
+        match detach {
+            true => {
+                println!(
+                    "[Detaching after {} from {} process {}]",
+                    call, which, other
+                )
+            }
+            false => println!(
+                "[Holding {} process {} stopped after {}]",
+                which, other, call
+            ),
+        }
+        self.inner.follow_fork(child, vfork, follow_child, detach)?;
+        Ok(())
+    }
+
+    /// Load the program the process executed, resolving breakpoint locations
+    /// in it, and moving those set by address in functions of the old one to
+    /// the same functions of the new one
+    fn follow_exec(&mut self) -> Result<()> {
+        let pid = self.inner.proc()?.pid();
+        let path = std::fs::read_link(format!("/proc/{}/exe", pid))?;
+        println!(
+            "process {} is executing new program: {}",
+            pid,
+            path.display()
+        );
+        for (num, bp) in self.breakpoints.iter_mut() {
+            if bp.kind.is_watchpoint() || self.locations.contains_key(num) {
+                continue;
+            }
+            if let Some((name, offset)) =
+                exec_symbol(self.inner.binary(), bp.addr)
+            {
+                bp.what = match offset {
+                    0 => name.clone(),
+                    _ => format!("{}+{}", name, offset),
+                };
+                bp.pending = true;
+                self.locations
+                    .insert(*num, Location::Symbol { name, offset });
+            }
+        }
+        let bias = self.binary().bias;
+        self.inner.exec(Binary::new(&path)?)?;
+        self.relocate_breakpoints(bias, self.binary().bias);
+        self.resolve_breakpoints();
+        env::arm_breakpoints(&mut self.breakpoints, &mut self.inner)?;
+        self.refresh_watched()
+    }
+
+    /// Announce a stop in another thread than the one resumed, gdb-style,
+    /// before the first line reporting it
+    fn announce_thread(&mut self, resumed: &mut Option<Pid>) -> Result<()> {
+        if let Some(resumed) = resumed.take() {
+            let (_, tid) = self.inner.thread()?;
+            if tid != resumed {
+                println!("[Switching to LWP {}]", tid);
+            }
+        }
+        Ok(())
+    }
+
+    /// Check for enabled software watchpoints
+    fn software_watching(&self) -> bool {
+        self.breakpoints
+            .values()
+            .any(|bp| bp.enabled && bp.kind == Kind::SoftwareWatch)
+    }
+
+    /// Report the breakpoints and watchpoints that stopped the process,
+    /// gdb-style. Returns false when nothing should stop it, e.g. writes
+    /// leaving a watched value unchanged, or a single step that hit nothing.
+    fn report_stop(&mut self, stepping: bool, resumed: Pid) -> Result<bool> {
+        let mut resumed = Some(resumed);
+        let pc = self.inner.pc()?;
+        let hits = self.inner.hw_hits()?;
+        let frame = env::frame_description(self.binary(), pc);
+        let nums: Vec<usize> = self
+            .breakpoints
+            .iter()
+            .filter(|(_, bp)| bp.enabled && !bp.pending)
+            .filter(|(_, bp)| match bp.kind {
+                Kind::Software => bp.addr == pc,
+                // Stepping onto the address steps over the trap
+                Kind::Hardware if stepping => bp.addr == pc,
+                Kind::SoftwareWatch => true,
+                kind => hits.contains(&Slot {
+                    addr: bp.addr,
+                    len: bp.len,
+                    kind,
+                }),
+            })
+            .map(|(num, _)| *num)
+            .collect();
+
+        // Stops other than our own traps are always reported
+        let trapped = self
+            .breakpoints
+            .values()
+            .any(|bp| bp.enabled && bp.kind == Kind::Software && bp.addr == pc);
+        let mut stopped = hits.is_empty() && !stepping && !trapped;
+        let mut watched = false;
+        let mut temporary = Vec::new();
+        for num in nums {
+            let bp = &self.breakpoints[&num];
+            if !bp.kind.is_watchpoint() {
+                if !self.count_hit(num) {
+                    continue;
                 }
-                DebugEvent::Stopped => {
-                    let pc = self.inner.pc()?;
-                    for (num, bp) in self.breakpoints.iter_mut() {
-                        if bp.addr == pc {
-                            println!("Breakpoint {}, 0x{:x} in ??", num, pc);
-                        }
+                self.announce_thread(&mut resumed)?;
+                let bp = &self.breakpoints[&num];
+                match bp.temporary {
+                    true => {
+                        println!("Temporary breakpoint {}, {}", num, frame);
+                        temporary.push(num);
                     }
+                    false => println!("Breakpoint {}, {}", num, frame),
                 }
-                e => {
-                    dbg!(e);
+                self.stop_commands.extend(bp.commands.clone());
+                stopped = true;
+                continue;
+            }
+            let kind = bp.kind;
+            let title = env::watchpoint_title(kind);
+            let title = format!("{} {}: {}", title, num, bp.what);
+            let old = self.watched[&num].value.clone();
+            let new = self.watched_value(num)?;
+            self.watched[&num].value = new.clone();
+            // x86 can't trap reads alone, so a changed value was a write
+            let changed = new != old;
+            match (kind, changed) {
+                (Kind::Watch, false)
+                | (Kind::SoftwareWatch, false)
+                | (Kind::ReadWatch, true) => continue,
+                _ => (),
+            }
+            if !self.count_hit(num) {
+                continue;
+            }
+            self.announce_thread(&mut resumed)?;
+            let bin = Some(self.binary());
+            println!();
+            println!("{}", title);
+            println!();
+            if changed {
+                println!("Old value = {}", expr::format_value(bin, &old, None));
+                println!("New value = {}", expr::format_value(bin, &new, None));
+            } else {
+                println!("Value = {}", expr::format_value(bin, &new, None));
+            }
+            let commands = self.breakpoints[&num].commands.clone();
+            self.stop_commands.extend(commands);
+            stopped = true;
+            watched = true;
+        }
+        if watched {
+            println!("{}", frame);
+        }
+        for num in temporary {
+            if let Some(mut bp) = self.remove_breakpoint(num) {
+                bp.disable(&mut self.inner)?;
+            }
+        }
+        Ok(stopped)
+    }
+
+    /// Report a signal the process received, gdb-style, as its policy says,
+    /// queueing it for delivery if passed. Returns whether to stop.
+    fn report_signal(&mut self, signal: Signal, resumed: Pid) -> Result<bool> {
+        let mut resumed = Some(resumed);
+        let policy = self.signals().get(signal);
+        if policy.pass {
+            self.inner.pass_signal(signal)?;
+        }
+        if policy.print || policy.stop {
+            self.announce_thread(&mut resumed)?;
+        }
+        if policy.print {
+            println!();
+            println!(
+                "Program received signal {}, {}.",
+                signal,
+                signal::description(signal)
+            );
+            if let Signal::SIGSEGV | Signal::SIGBUS = signal {
+                println!("Fault address {:#x}.", self.inner.fault_address()?);
+            }
+        }
+        if policy.stop {
+            let pc = self.inner.pc()?;
+            println!("{}", env::frame_description(self.binary(), pc));
+        }
+        Ok(policy.stop)
+    }
+
+    /// Count a breakpoint hit, checking its condition and ignore count, and
+    /// return whether to stop
+    fn count_hit(&mut self, num: usize) -> bool {
+        if let Some(cond) = self.breakpoints[&num].condition.clone() {
+            match self.test_condition(&cond) {
+                Ok(false) => return false,
+                Ok(true) => (),
+                Err(e) => {
+                    println!(
+                        "Error in testing condition for breakpoint {}:",
+                        num
+                    );
+                    println!("{}", e);
+                    self.breakpoints[&num].hits += 1;
+                    return true;
                 }
             }
         }
-        Ok(None)
+        let bp = &mut self.breakpoints[&num];
+        bp.hits += 1;
+        if bp.ignore > 0 {
+            bp.ignore -= 1;
+            return false;
+        }
+        true
+    }
+
+    /// Evaluate a breakpoint condition in the stopped process
+    fn test_condition(&mut self, cond: &str) -> Result<bool> {
+        let expr = cli::parse_expr(cond)?;
+        Ok(expr::evaluate(self, &expr)?.as_i64() != 0)
+    }
+
+    /// Current value of a watchpoint, read from its watched memory, or for a
+    /// software watchpoint by evaluating its expression
+    fn watched_value(&mut self, num: usize) -> Result<Value> {
+        let (kind, addr) =
+            (self.breakpoints[&num].kind, self.breakpoints[&num].addr);
+        match kind {
+            Kind::SoftwareWatch => {
+                let expr = self.watched[&num].expr.clone();
+                expr::evaluate(self, &expr)
+            }
+            _ => {
+                let ty = self.watched[&num].value.ty.clone();
+                Ok(Value::from_bytes(&self.load(addr, ty.size())?, ty))
+            }
+        }
     }
 
     fn stepi_command(&mut self, n: usize) -> Result<Option<Event>> {
         for _ in 0..n {
-            self.inner.step()?;
+            let (_, resumed) = self.inner.thread()?;
+            match self.resume_process(true)? {
+                event @ DebugEvent::Exited(..)
+                | event @ DebugEvent::Terminated(..) => {
+                    return Ok(Some(Event::Process(event)));
+                }
+                DebugEvent::Received(signal) => {
+                    if self.report_signal(signal, resumed)? {
+                        return Ok(None);
+                    }
+                }
+                _ => {
+                    self.report_stop(true, resumed)?;
+                }
+            }
         }
         Ok(None)
     }
@@ -78,10 +488,12 @@ impl Env<Debugger> {
                 for (_, mut bp) in self.breakpoints.drain(..) {
                     bp.disable(&mut self.inner)?;
                 }
+                self.locations.clear();
+                self.watched.clear();
             }
         } else {
             for num in args {
-                match self.breakpoints.remove(&num) {
+                match self.remove_breakpoint(num) {
                     Some(mut bp) => bp.disable(&mut self.inner)?,
                     None => println!("No breakpoint number {}.", num),
                 }
@@ -110,152 +522,375 @@ impl Env<Debugger> {
         Ok(None)
     }
 
-    fn examine_command(
-        &mut self,
-        fmt: Option<Fmt>,
-        addr: Option<usize>,
-    ) -> Result<Option<Event>> {
-        // Update last used format and address
-        if let Some(fmt) = fmt {
-            self.set_fmt(fmt)?;
-        }
-        if let Some(addr) = addr {
-            self.set_addr(addr)?;
+    fn file_command(&mut self, path: PathBuf) -> Result<Option<Event>> {
+        println!("A program is being debugged already.");
+        if cli::prompt_yes_no("Are you sure you want to change the file?") {
+            self.set_file(path)
+        } else {
+            println!("File not changed.");
+            Ok(None)
         }
+    }
 
-        let mut addr = match self.addr() {
-            Some(addr) => addr,
-            None => bail!("Argument required (starting display address)."),
-        };
-
-        // Unwrap format with defaults
-        let fmt = self.fmt();
-        let _reverse = fmt.reverse;
-        let repeat = fmt.repeat.unwrap_or(1);
-        let size = fmt.size.unwrap_or('w');
-        let format = fmt.format.unwrap_or('x');
-
-        // Convert size char to byte size and column count
-        let (step, size) = match size {
-            'b' => (8, 1),
-            'h' => (8, 2),
-            'w' => (4, 4),
-            'g' => (2, 8),
-            _ => unreachable!(),
+    /// Attach to another process, detaching the one attached to or killing
+    /// the one started
+    fn attach_command(&mut self, pid: Pid) -> Result<Option<Event>> {
+        let (action, declined) = match self.inner.attached()? {
+            true => ("Detach", "Not detached."),
+            false => ("Kill", "Not killed."),
         };
+        let prompt =
+            format!("A program is being debugged already.  {} it?", action);
+        if !cli::prompt_yes_no(prompt) {
+            bail!(declined);
+        }
+        // Only let go of the process once there's another to attach to
+        let event = self.attach_process(pid)?;
+        self.inner.release()?;
+        Ok(event)
+    }
 
-        let mut i = 0;
-        while i < repeat {
-            let mut j = 0;
-            print!("{:#x}: ", addr);
-            while j < step && (i + j) < repeat {
-                // Read bytes
-                let mut bytes = [0; 8];
-
-                self.inner
-                    .read(addr, size)?
-                    .iter()
-                    .enumerate()
-                    .for_each(|(i, byte)| bytes[i] = *byte);
-
-                match format {
-                    'x' => print!(
-                        " {:0width$x}",
-                        usize::from_le_bytes(bytes),
-                        width = size * 2
-                    ),
-                    _ => unimplemented!(),
-                }
+    fn detach_command(&mut self) -> Result<Option<Event>> {
+        let pid = self.inner.proc()?.pid();
+        let path = self.binary().path.display().to_string();
+        println!("Detaching from program: {}, process {}", path, pid);
+        Ok(Some(Event::Process(self.inner.detach()?)))
+    }
 
-                j += 1;
-                addr += size;
+    /// Show the current thread, or select another by number
+    fn thread_command(&mut self, num: Option<usize>) -> Result<Option<Event>> {
+        let num = match num {
+            Some(num) => num,
+            None => {
+                let (num, tid) = self.inner.thread()?;
+                println!("[Current thread is {} (LWP {})]", num, tid);
+                return Ok(None);
             }
-            println!();
-            i += j;
+        };
+        let threads = self.inner.threads()?;
+        let tid = match threads.into_iter().find(|(n, _)| *n == num) {
+            Some((_, tid)) => tid,
+            None => bail!("Invalid thread ID: {}", num),
+        };
+        self.inner.select_thread(tid)?;
+        println!("[Switching to thread {} (LWP {})]", num, tid);
+        let pc = self.inner.pc()?;
+        println!("#0  {}", env::frame_description(self.binary(), pc));
+        Ok(None)
+    }
+
+    fn kill_command(&mut self) -> Result<Option<Event>> {
+        if !cli::prompt_yes_no("Kill the program being debugged?") {
+            bail!("Not confirmed.");
         }
+        Ok(Some(Event::Process(self.inner.kill()?)))
+    }
 
-        self.set_addr(addr)?;
+    fn run_command(&mut self, args: Vec<String>) -> Result<Option<Event>> {
+        self.restart(args)?;
         Ok(None)
     }
 
-    fn file_command(&mut self, path: PathBuf) -> Result<Option<Event>> {
-        println!("A program is being debugged already.");
-        if cli::prompt_yes_no("Are you sure you want to change the file?") {
-            self.set_file(path)
-        } else {
-            println!("File not changed.");
-            Ok(None)
+    /// Restart, running to a temporary breakpoint at main
+    fn start_command(&mut self, args: Vec<String>) -> Result<Option<Event>> {
+        if !self.restart(args)? {
+            return Ok(None);
         }
+        self.break_command(env::main_location(), None, true)?;
+        self.resume(1)
     }
 
-    fn run_command(&mut self, args: Vec<String>) -> Result<Option<Event>> {
-        // Prompt to restart program
+    /// Restart, reporting the stop at the program's first instruction
+    fn starti_command(&mut self, args: Vec<String>) -> Result<Option<Event>> {
+        if !self.restart(args)? {
+            return Ok(None);
+        }
+        let pc = self.inner.pc()?;
+        println!();
+        println!("Program stopped.");
+        println!("{}", env::frame_description(self.binary(), pc));
+        Ok(None)
+    }
+
+    /// Prompt to restart the program, stopped at its first instruction,
+    /// returning whether it was
+    fn restart(&mut self, args: Vec<String>) -> Result<bool> {
         println!("The program being debugged has been started already.");
-        if cli::prompt_yes_no("Start it from the beginning?") {
-            if args.len() > 0 {
-                self.set_args(args)?;
-            }
-            self.inner.run(self.args())
-        } else {
+        if !cli::prompt_yes_no("Start it from the beginning?") {
             println!("Program not restarted.");
+            return Ok(false);
         }
-        Ok(None)
+        if !args.is_empty() {
+            self.set_args(args)?;
+        }
+        let bias = self.binary().bias;
+        self.inner.run(self.args(), self.disable_randomization())?;
+        self.relocate_breakpoints(bias, self.binary().bias);
+        env::arm_breakpoints(&mut self.breakpoints, &mut self.inner)?;
+        self.refresh_watched()?;
+        Ok(true)
     }
 
-    fn repeat_command(&mut self) -> Result<Option<Event>> {
-        Ok(None)
+    /// Take watched values from a new process
+    pub fn refresh_watched(&mut self) -> Result<()> {
+        let nums: Vec<usize> = self.watched.keys().cloned().collect();
+        for num in nums {
+            self.watched[&num].value = self.watched_value(num)?;
+        }
+        Ok(())
     }
 
     fn info_command(&mut self, cmd: cli::Info) -> Result<Option<Event>> {
         match cmd {
             cli::Info::Proc { cmd } => self.info_proc_command(cmd)?,
+            cli::Info::Signals { signal } => {
+                self.info_signals_command(signal)?
+            }
             cli::Info::Breakpoints { args } => {
-                self.info_breakpoints_command(args)?
+                self.info_breakpoints_command(args, false)?
+            }
+            cli::Info::Watchpoints { args } => {
+                self.info_breakpoints_command(args, true)?
             }
             cli::Info::Registers { names } => {
-                self.info_registers_command(names)?
+                self.info_registers_command(names, false)?
+            }
+            cli::Info::Threads => self.info_threads_command()?,
+            cli::Info::AllRegisters { names } => {
+                self.info_registers_command(names, true)?
             }
         };
         Ok(None)
     }
 
+    /// List threads gdb-style, marking the current one
+    fn info_threads_command(&mut self) -> Result<Option<Event>> {
+        let (current, selected) = self.inner.thread()?;
+        let proc = self.inner.proc()?;
+        let mut rows = Vec::new();
+        for (num, tid) in self.inner.threads()? {
+            self.inner.select_thread(tid)?;
+            let pc = self.inner.pc()?;
+            let target = format!("LWP {} \"{}\"", tid, proc.thread_name(tid)?);
+            let frame = env::frame_description(self.binary(), pc);
+            rows.push((num, target, frame));
+        }
+        self.inner.select_thread(selected)?;
+
+        let width = rows.iter().map(|(_, target, _)| target.len()).max();
+        let width = width.unwrap_or(0).max("Target Id".len());
+        println!("  {:<4} {:<w$} Frame", "Id", "Target Id", w = width);
+        for (num, target, frame) in rows {
+            let mark = match num == current {
+                true => "*",
+                false => " ",
+            };
+            println!("{} {:<4} {:<w$} {}", mark, num, target, frame, w = width);
+        }
+        Ok(None)
+    }
+
     fn info_proc_command(&mut self, cmd: cli::Proc) -> Result<Option<Event>> {
         let proc = self.inner.proc()?;
         match cmd {
             cli::Proc::Mappings => {
-                dbg!(proc.proc_maps()?);
+                println!("process {}", proc.pid());
+                println!("Mapped address spaces:");
+                println!();
+                println!(
+                    "{:>18} {:>18} {:>10} {:>10}  {:5}  objfile",
+                    "Start Addr", "End Addr", "Size", "Offset", "Perms"
+                );
+                for map in proc.proc_maps()? {
+                    println!(
+                        "{:>18} {:>18} {:>10} {:>10}  {:5}  {}",
+                        format!("{:#x}", map.address_range.start),
+                        format!("{:#x}", map.address_range.end),
+                        format!("{:#x}", map.size()),
+                        format!("{:#x}", map.offset),
+                        map.perms,
+                        map.pathname.display(),
+                    );
+                }
             }
         }
         Ok(None)
     }
 
-    fn info_breakpoints_command(
+    /// Print registers gdb-style, the general purpose ones by default, all
+    /// of them with `all`, or those named
+    fn info_registers_command(
         &mut self,
-        _args: Vec<usize>,
+        names: Vec<String>,
+        all: bool,
     ) -> Result<Option<Event>> {
-        for (num, bp) in self.breakpoints.iter() {
-            let enb = match bp.enabled {
-                true => "y",
-                false => "n",
-            };
-            println!(
-                "{:10} {:16} {:4} {:3} {:20} {}",
-                "Num", "Type", "Disp", "Enb", "Address", "What"
-            );
-            println!(
-                "{:<10} {:16} {:4} {:3} 0x{:018x} {}",
-                num, "breakpoint", "keep", enb, bp.addr, "",
-            );
+        let regs = self.inner.registers()?;
+        let mut rows = register_rows(self.binary(), &regs);
+        if all || !names.is_empty() {
+            let fpregs = self.inner.fp_registers()?;
+            rows.extend(fp_register_rows(&fpregs));
+            // AVX registers alias the SSE ones, like gdb only list the wider
+            if fpregs.ymm(0).is_some() && names.is_empty() {
+                rows.retain(|(name, _)| !name.starts_with("xmm"));
+            }
+        }
+
+        if names.is_empty() {
+            for (name, value) in rows {
+                println!("{:<15}{}", name, value);
+            }
+            return Ok(None);
+        }
+
+        for name in names {
+            let name = name.trim_start_matches('$');
+            let reg = regs::alias(name);
+            match rows.iter().find(|(row, _)| row == reg) {
+                Some((_, value)) => println!("{:<15}{}", name, value),
+                None => bail!("Invalid register `{}'", name),
+            }
         }
         Ok(None)
     }
+}
 
-    fn info_registers_command(
-        &mut self,
-        _names: Vec<String>,
-    ) -> Result<Option<Event>> {
-        let pc = self.inner.pc()?;
-        println!("{:<15} 0x{:x}   {}", "rip", pc, pc);
-        Ok(None)
+/// Format general purpose registers as raw and natural values, e.g.
+/// ("rip", "0x401126            0x401126 <main+4>")
+fn register_rows(bin: &Binary, regs: &Registers) -> Vec<(String, String)> {
+    regs.iter()
+        .map(|(name, value)| {
+            let natural = match name {
+                "rip" => match env::symbol_offset(bin, value as Address) {
+                    Some(sym) => format!("{:#x} {}", value, sym),
+                    None => format!("{:#x}", value),
+                },
+                "rbp" | "rsp" => format!("{:#x}", value),
+                "eflags" => {
+                    format!("[ {} ]", regs::eflags_names(value).join(" "))
+                }
+                _ => (value as i64).to_string(),
+            };
+            (
+                name.to_owned(),
+                format!("{:<19}{}", format!("{:#x}", value), natural),
+            )
+        })
+        .collect()
+}
+
+/// Format x87, SSE and AVX registers
+fn fp_register_rows(fpregs: &FpRegisters) -> Vec<(String, String)> {
+    let mut rows = Vec::new();
+    for n in 0..8 {
+        let raw = fpregs.st(n);
+        let hex: String =
+            raw.iter().rev().map(|b| format!("{:02x}", b)).collect();
+        let value = regs::extended_to_f64(raw);
+        rows.push((
+            format!("st{}", n),
+            format!("{:<19}(raw 0x{})", value, hex),
+        ));
+    }
+    for (name, value) in fpregs.control() {
+        rows.push((
+            name.to_owned(),
+            format!("{:<19}{}", format!("{:#x}", value), value),
+        ));
+    }
+    let mxcsr = fpregs.mxcsr();
+    rows.push((
+        "mxcsr".to_owned(),
+        format!(
+            "{:<19}[ {} ]",
+            format!("{:#x}", mxcsr),
+            regs::mxcsr_names(mxcsr).join(" ")
+        ),
+    ));
+    for n in 0..16 {
+        let xmm = fpregs.xmm(n);
+        let int32: Vec<_> = (0..4)
+            .map(|i| format!("{:#x}", (xmm >> (i * 32)) as u32))
+            .collect();
+        let int64: Vec<_> = (0..2)
+            .map(|i| format!("{:#x}", (xmm >> (i * 64)) as u64))
+            .collect();
+        rows.push((
+            format!("xmm{}", n),
+            format!(
+                "{{v4_int32 = {{{}}}, v2_int64 = {{{}}}, uint128 = {:#x}}}",
+                int32.join(", "),
+                int64.join(", "),
+                xmm
+            ),
+        ));
+    }
+    for n in 0..16 {
+        if let Some((low, high)) = fpregs.ymm(n) {
+            let int32: Vec<_> = (0..8)
+                .map(|i| {
+                    let half = if i < 4 { low } else { high };
+                    format!("{:#x}", (half >> (i % 4 * 32)) as u32)
+                })
+                .collect();
+            let int64: Vec<_> = (0..4)
+                .map(|i| {
+                    let half = if i < 2 { low } else { high };
+                    format!("{:#x}", (half >> (i % 2 * 64)) as u64)
+                })
+                .collect();
+            rows.push((
+                format!("ymm{}", n),
+                format!(
+                    "{{v8_int32 = {{{}}}, v4_int64 = {{{}}}, \
+                     v2_int128 = {{{:#x}, {:#x}}}}}",
+                    int32.join(", "),
+                    int64.join(", "),
+                    low,
+                    high
+                ),
+            ));
+        }
+    }
+    rows
+}
+
+/// Function and offset of a breakpoint address in a program about to be
+/// replaced by another it executes, with no offset for an address just past
+/// the prologue, where `break FUNCTION` stops
+fn exec_symbol(bin: &Binary, addr: Address) -> Option<(String, usize)> {
+    let (sym, offset) = bin.symbolize(addr)?;
+    let past_prologue = bin
+        .function_range(sym.addr)
+        .and_then(|(_, range)| bin.lines.skip_prologue(range.start, range.end));
+    let offset = match past_prologue == Some(addr) {
+        true => 0,
+        false => offset,
+    };
+    Some((sym.name.clone(), offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{LineTable, Symbol, SymbolKind};
+
+    #[test]
+    fn test_exec_symbol() {
+        let foo = Symbol {
+            name: "foo".to_owned(),
+            addr: 0x1000,
+            size: 0x20,
+            kind: SymbolKind::Function,
+        };
+        // The prologue is line 1, up to the second line at 0x1004
+        let rows = [(0x1000, 1), (0x1004, 2), (0x1010, 3)];
+        let lines = LineTable::sequence("foo.c", &rows, 0x1020);
+        let bin = Binary::fixture(0x1000..0x1020, vec![foo], lines);
+        let symbol = |addr| exec_symbol(&bin, addr);
+        assert_eq!(symbol(0x1000), Some(("foo".to_owned(), 0)));
+        assert_eq!(symbol(0x1004), Some(("foo".to_owned(), 0)));
+        assert_eq!(symbol(0x1002), Some(("foo".to_owned(), 2)));
+        assert_eq!(symbol(0x1010), Some(("foo".to_owned(), 0x10)));
+        assert_eq!(symbol(0x1020), None);
     }
 }
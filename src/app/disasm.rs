@@ -0,0 +1,156 @@
+use std::ops::Range;
+
+use capstone::prelude::*;
+
+use super::*;
+
+/// Decoded instruction, copied out of capstone's buffers
+#[derive(Debug)]
+pub struct Instruction {
+    pub addr: Address,
+    pub len: usize,
+    pub mnemonic: String,
+    pub operands: String,
+}
+
+/// x86 disassembler for an object file's architecture, in AT&T syntax like
+/// gdb's default
+pub struct Disassembler<'a> {
+    cs: Capstone<'a>,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(bin: &Binary) -> Result<Self> {
+        let mode = match bin.is_64 {
+            true => arch::x86::ArchMode::Mode64,
+            false => arch::x86::ArchMode::Mode32,
+        };
+        let cs = Capstone::new()
+            .x86()
+            .mode(mode)
+            .syntax(arch::x86::ArchSyntax::Att)
+            .build()?;
+        Ok(Disassembler { cs })
+    }
+
+    /// Decode up to `count` instructions from code at an address, or all of
+    /// them if `count` is zero
+    pub fn disassemble(
+        &mut self,
+        code: &[u8],
+        addr: Address,
+        count: usize,
+    ) -> Result<Vec<Instruction>> {
+        let insns = match count {
+            0 => self.cs.disasm_all(code, addr as u64)?,
+            n => self.cs.disasm_count(code, addr as u64, n)?,
+        };
+        Ok(insns
+            .iter()
+            .map(|insn| Instruction {
+                addr: insn.address() as Address,
+                len: insn.bytes().len(),
+                mnemonic: insn.mnemonic().unwrap_or_default().to_owned(),
+                // gdb doesn't space operands
+                operands: insn.op_str().unwrap_or_default().replace(", ", ","),
+            })
+            .collect())
+    }
+
+    /// Decode one instruction, or gdb's "(bad)" for an invalid byte
+    pub fn decode(
+        &mut self,
+        code: &[u8],
+        addr: Address,
+    ) -> Result<Instruction> {
+        let insn = self.disassemble(code, addr, 1)?.pop();
+        Ok(insn.unwrap_or_else(|| Instruction {
+            addr,
+            len: 1,
+            mnemonic: "(bad)".to_owned(),
+            operands: String::new(),
+        }))
+    }
+}
+
+impl Instruction {
+    /// Format mnemonic and operands, annotating direct branch targets with
+    /// their symbol, e.g. "call   0x401126 <main>"
+    pub fn text(&self, bin: &Binary) -> String {
+        let mut text = format!("{:6} {}", self.mnemonic, self.operands);
+        let branch = self.mnemonic.starts_with('j')
+            || self.mnemonic.starts_with("call")
+            || self.mnemonic.starts_with("loop");
+        let target = self
+            .operands
+            .strip_prefix("0x")
+            .and_then(|hex| usize::from_str_radix(hex, 16).ok());
+        if let (true, Some(target)) = (branch, target) {
+            if let Some(sym) = env::symbol_offset(bin, target) {
+                text.push_str(&format!(" {}", sym));
+            }
+        }
+        text.trim_end().to_owned()
+    }
+
+    /// Print an instruction gdb-style, with "=>" marking the current pc
+    pub fn print(&self, bin: &Binary, pc: Option<Address>, label: Label) {
+        let marker = match pc {
+            Some(pc) if pc == self.addr => "=>",
+            _ => "  ",
+        };
+        let label = match (label, bin.symbolize(self.addr)) {
+            (Label::Symbol, Some((sym, 0))) => format!(" <{}>", sym.name),
+            (Label::Symbol, Some((sym, off)))
+            | (Label::Range, Some((sym, off))) => {
+                format!(" <{}+{}>", sym.name, off)
+            }
+            (Label::Offset, Some((_, off))) => format!(" <+{}>", off),
+            (_, None) => String::new(),
+        };
+        println!("{} {:#x}{}:\t{}", marker, self.addr, label, self.text(bin));
+    }
+}
+
+/// Address label style, which differs between gdb's x/i and disassemble
+#[derive(Clone, Copy)]
+pub enum Label {
+    /// Symbol and offset, e.g. "<main+4>", or "<main>" at offset zero
+    Symbol,
+    /// Offset into the function being dumped, e.g. "<+4>"
+    Offset,
+    /// Symbol and offset, including "+0"
+    Range,
+}
+
+/// Disassemble and print the instructions starting in an address range,
+/// gdb-style. The code may extend past the range to complete the last
+/// instruction. When `func` is given, the range is the whole function.
+pub fn print_code(
+    bin: &Binary,
+    code: &[u8],
+    range: Range<Address>,
+    func: Option<&str>,
+    pc: Option<Address>,
+) -> Result<()> {
+    let (addr, end) = (range.start, range.end);
+    let label = match func {
+        Some(name) => {
+            println!("Dump of assembler code for function {}:", name);
+            Label::Offset
+        }
+        None => {
+            println!("Dump of assembler code from {:#x} to {:#x}:", addr, end);
+            Label::Range
+        }
+    };
+    let mut dis = Disassembler::new(bin)?;
+    let mut offset = 0;
+    while offset < range.len() && offset < code.len() {
+        let insn = dis.decode(&code[offset..], addr + offset)?;
+        insn.print(bin, pc, label);
+        offset += insn.len;
+    }
+    println!("End of assembler dump.");
+    Ok(())
+}
@@ -4,12 +4,20 @@ use std::path::PathBuf;
 use failure::bail;
 use indexmap::IndexMap;
 
+use super::expr::{self, Scope, Value, Values};
+use super::signal::{self, Signals};
 use super::*;
+use crate::cli::ForkMode;
+use crate::dbg::hw::{self, Kind, Slot};
 
 #[derive(Debug, Clone)]
 struct Config {
     path: Option<PathBuf>,
     args: Vec<String>,
+    disable_randomization: bool,
+    signals: Signals,
+    follow_fork_mode: ForkMode,
+    detach_on_fork: bool,
 }
 
 impl Config {
@@ -17,10 +25,21 @@ impl Config {
         Config {
             path: opt.prog.clone(),
             args: opt.args.clone(),
+            disable_randomization: true,
+            signals: Default::default(),
+            follow_fork_mode: ForkMode::Parent,
+            detach_on_fork: true,
         }
     }
 }
 
+/// Watchpoint expression and the last value seen
+#[derive(Debug)]
+pub struct Watched {
+    pub expr: Expr,
+    pub value: Value,
+}
+
 /// Environment covers an inner analysis state and applicaton configuration
 #[derive(Debug)]
 pub struct Env<T> {
@@ -31,9 +50,17 @@ pub struct Env<T> {
     // Breakpoints
     pub(super) breakpoints: IndexMap<usize, Breakpoint>,
     next_breakpoint_id: usize,
+    // Symbolic location specs of breakpoints, resolved again in each file
+    pub(super) locations: IndexMap<usize, Location>,
+    // Watchpoint expressions and values
+    pub(super) watched: IndexMap<usize, Watched>,
+    // Command lists of the breakpoints last stopped at, to run next
+    pub(super) stop_commands: Vec<String>,
     // Previous format options
     last_fmt: Fmt,
     last_addr: Option<usize>,
+    // Convenience variables and value history
+    pub(super) values: Values,
 }
 
 impl<T> Deref for Env<T> {
@@ -47,13 +74,22 @@ impl<T> Deref for Env<T> {
 impl<T> Env<T> {
     /// Context switch
     pub fn into_context<U>(self, ctx: U) -> Env<U> {
+        self.map_context(|_| ctx)
+    }
+
+    /// Context switch to a context made from this one
+    pub fn map_context<U, F: FnOnce(T) -> U>(self, f: F) -> Env<U> {
         Env {
-            inner: ctx,
+            inner: f(self.inner),
             config: self.config,
             breakpoints: self.breakpoints,
             next_breakpoint_id: self.next_breakpoint_id,
+            locations: self.locations,
+            watched: self.watched,
+            stop_commands: self.stop_commands,
             last_fmt: self.last_fmt,
             last_addr: self.last_addr,
+            values: self.values,
         }
     }
 
@@ -70,9 +106,25 @@ impl<T> Env<T> {
     pub fn set_file(&mut self, path: PathBuf) -> Result<Option<Event>> {
         self.config.path = Some(path.clone());
         let bin = Binary::new(path)?;
+        println!("Reading symbols from {}...", bin.path.display());
         Ok(Some(Event::Open(bin)))
     }
 
+    /// Load the executable of a running process to attach to it
+    pub fn attach_process(&mut self, pid: Pid) -> Result<Option<Event>> {
+        let path = match std::fs::read_link(format!("/proc/{}/exe", pid)) {
+            Ok(path) => path,
+            Err(_) => {
+                bail!("Can't attach to process {}: No such process.", pid)
+            }
+        };
+        println!("Attaching to process {}", pid);
+        let bin = Binary::new(&path)?;
+        self.config.path = Some(path);
+        println!("Reading symbols from {}...", bin.path.display());
+        Ok(Some(Event::Attach(bin, pid)))
+    }
+
     pub fn args(&self) -> Vec<String> {
         self.config.args.clone()
     }
@@ -82,6 +134,162 @@ impl<T> Env<T> {
         Ok(None)
     }
 
+    pub fn disable_randomization(&self) -> bool {
+        self.config.disable_randomization
+    }
+
+    /// Whether to debug the child instead of the parent after a fork
+    pub fn follow_fork_child(&self) -> bool {
+        self.config.follow_fork_mode == ForkMode::Child
+    }
+
+    pub fn detach_on_fork(&self) -> bool {
+        self.config.detach_on_fork
+    }
+
+    pub fn signals(&self) -> &Signals {
+        &self.config.signals
+    }
+
+    /// Change how signals are handled, then show their policies
+    pub fn handle_signals_command(
+        &mut self,
+        args: Vec<String>,
+    ) -> Result<Option<Event>> {
+        let signals = self.config.signals.handle(&args)?;
+        self.config.signals.print(&signals);
+        Ok(None)
+    }
+
+    /// Show how all signals are handled, or just one
+    pub fn info_signals_command(
+        &mut self,
+        signal: Option<String>,
+    ) -> Result<Option<Event>> {
+        let signals = match signal {
+            Some(arg) => match signal::parse_signal(&arg) {
+                Some(signal) => vec![signal],
+                None => bail!(
+                    "Only signals 1-15 are valid as numeric signals.\n\
+                     Use \"info signals\" for a list of symbolic signals."
+                ),
+            },
+            None => Vec::new(),
+        };
+        self.config.signals.print(&signals);
+        Ok(None)
+    }
+
+    /// List breakpoints and watchpoints, or only watchpoints
+    pub fn info_breakpoints_command(
+        &mut self,
+        _args: Vec<usize>,
+        watchpoints: bool,
+    ) -> Result<Option<Event>> {
+        let rows: Vec<_> = self
+            .breakpoints
+            .iter()
+            .filter(|(_, bp)| !watchpoints || bp.kind.is_watchpoint())
+            .collect();
+        if rows.is_empty() {
+            match watchpoints {
+                true => println!("No watchpoints."),
+                false => println!("No breakpoints or watchpoints."),
+            }
+            return Ok(None);
+        }
+        println!(
+            "{:10} {:16} {:4} {:3} {:20} What",
+            "Num", "Type", "Disp", "Enb", "Address"
+        );
+        for (num, bp) in rows.iter() {
+            let enb = match bp.enabled {
+                true => "y",
+                false => "n",
+            };
+            let addr = match bp.pending {
+                _ if bp.kind.is_watchpoint() => String::new(),
+                true => "<PENDING>".to_owned(),
+                false => format!("0x{:018x}", bp.addr),
+            };
+            let ty = match bp.kind {
+                Kind::Software => "breakpoint",
+                Kind::Hardware => "hw breakpoint",
+                Kind::Watch => "hw watchpoint",
+                Kind::ReadWatch => "read watchpoint",
+                Kind::AccessWatch => "acc watchpoint",
+                Kind::SoftwareWatch => "watchpoint",
+            };
+            let disp = match bp.temporary {
+                true => "del",
+                false => "keep",
+            };
+            println!(
+                "{:<10} {:16} {:4} {:3} {:20} {}",
+                num, ty, disp, enb, addr, bp.what,
+            );
+            if let Some(cond) = &bp.condition {
+                println!("\tstop only if {}", cond);
+            }
+            match bp.hits {
+                0 => (),
+                1 => println!("\tbreakpoint already hit 1 time"),
+                n => println!("\tbreakpoint already hit {} times", n),
+            }
+            if bp.ignore > 0 {
+                println!(
+                    "\tWill ignore next {} crossings of breakpoint.",
+                    bp.ignore
+                );
+            }
+            for line in bp.commands.iter() {
+                println!("        {}", line);
+            }
+        }
+        if rows.iter().any(|(_, bp)| bp.kind == Kind::SoftwareWatch) {
+            println!();
+            println!(
+                "Software watchpoints single-step the program, which runs \
+                 it many times slower."
+            );
+        }
+        Ok(None)
+    }
+
+    /// Show information that doesn't need a process
+    pub fn info_without_process(
+        &mut self,
+        cmd: cli::Info,
+    ) -> Result<Option<Event>> {
+        match cmd {
+            cli::Info::Breakpoints { args } => {
+                self.info_breakpoints_command(args, false)
+            }
+            cli::Info::Watchpoints { args } => {
+                self.info_breakpoints_command(args, true)
+            }
+            cli::Info::Signals { signal } => self.info_signals_command(signal),
+            cli::Info::Registers { .. } | cli::Info::AllRegisters { .. } => {
+                bail!("The program has no registers now.")
+            }
+            cli::Info::Proc { .. } => {
+                bail!("No current process: you must name one.")
+            }
+            cli::Info::Threads => bail!("No threads."),
+        }
+    }
+
+    /// Handle `thread` without a process, so without threads
+    pub fn thread_without_process(
+        &mut self,
+        num: Option<usize>,
+    ) -> Result<Option<Event>> {
+        match num {
+            Some(num) => bail!("Invalid thread ID: {}", num),
+            None => bail!("No thread selected"),
+        }
+    }
+
     pub fn addr(&self) -> Option<usize> {
         self.last_addr
     }
@@ -100,30 +308,446 @@ impl<T> Env<T> {
         Ok(None)
     }
 
-    pub fn add_breakpoint(&mut self, loc: usize) -> usize {
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
         let num = self.next_breakpoint_id;
-        let breakpoint = Breakpoint::new(loc as Address);
         self.breakpoints.insert(num, breakpoint);
         self.next_breakpoint_id += 1;
         num
     }
 
+    /// Add a breakpoint set at a location, remembering a symbolic location to
+    /// resolve again when another file is loaded
+    pub fn add_location_breakpoint(
+        &mut self,
+        breakpoint: Breakpoint,
+        loc: &Location,
+    ) -> usize {
+        let num = self.add_breakpoint(breakpoint);
+        if let Location::Symbol { .. } | Location::Line { .. } = loc {
+            self.locations.insert(num, loc.clone());
+        }
+        num
+    }
+
+    /// Move breakpoints resolved against one load bias to another
+    pub fn relocate_breakpoints(&mut self, from: Address, to: Address) {
+        for bp in self.breakpoints.values_mut().filter(|bp| !bp.pending) {
+            bp.addr = bp.addr.wrapping_sub(from).wrapping_add(to);
+        }
+    }
+
+    /// Resolve the locations of breakpoints set by one in a newly loaded
+    /// file, making those it doesn't have pending
+    pub fn resolve_breakpoints(&mut self)
+    where
+        Self: Scope,
+    {
+        for (num, loc) in self.locations.clone() {
+            let resolved = location_breakpoint(self, &loc);
+            let bp = match self.breakpoints.get_mut(&num) {
+                Some(bp) => bp,
+                None => continue,
+            };
+            match resolved {
+                Ok(resolved) => {
+                    bp.addr = resolved.addr;
+                    bp.what = resolved.what;
+                    bp.pending = false;
+                }
+                Err(_) => {
+                    bp.what = location_spec(&loc);
+                    bp.pending = true;
+                }
+            }
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, num: usize) -> Option<Breakpoint> {
+        self.locations.remove(&num);
+        self.watched.remove(&num);
+        self.breakpoints.remove(&num)
+    }
+
+    /// Delete breakpoints when there is no process to remove them from
+    pub fn delete_breakpoints(
+        &mut self,
+        args: Vec<usize>,
+    ) -> Result<Option<Event>> {
+        for num in args {
+            if self.remove_breakpoint(num).is_none() {
+                println!("No breakpoint number {}.", num);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Enable or disable breakpoints when there is no process to insert them
+    /// into
+    pub fn enable_breakpoints(
+        &mut self,
+        args: Vec<usize>,
+        enabled: bool,
+    ) -> Result<Option<Event>> {
+        for num in args {
+            match self.breakpoints.get_mut(&num) {
+                Some(bp) => bp.enabled = enabled,
+                None => println!("No breakpoint number {}.", num),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Add a hardware assisted breakpoint at a location
+    pub fn add_hw_breakpoint(
+        &mut self,
+        loc: &Location,
+        cond: Option<String>,
+    ) -> Result<usize>
+    where
+        Self: Scope,
+    {
+        if !self.hw_slot_free() {
+            bail!("Hardware breakpoints used exceeds limit.");
+        }
+        let mut bp = location_breakpoint(self, loc)?;
+        bp.kind = Kind::Hardware;
+        bp.condition = check_condition(cond)?;
+        let num = self.add_location_breakpoint(bp, loc);
+        print_breakpoint(object(self)?, num, &self.breakpoints[&num]);
+        Ok(num)
+    }
+
+    /// Add a watchpoint, remembering its value. Lvalues in memory are
+    /// watched by a debug register if one is free and fits, otherwise watch
+    /// falls back to checking the value after every single step.
+    pub fn add_watchpoint(&mut self, text: String, kind: Kind) -> Result<usize>
+    where
+        Self: Scope,
+    {
+        let expr = cli::parse_expr(&text)?;
+        if expr::is_constant(&expr) {
+            bail!("Cannot watch constant value `{}'.", text);
+        }
+        let lvalue = expr::memory_lvalue(self, &expr)?
+            .filter(|(addr, ty)| Slot::fits(*addr, ty.size()));
+        let (mut bp, value) = match (lvalue, kind) {
+            (Some((addr, ty)), _) if self.hw_slot_free() => {
+                let value = Value::from_bytes(&self.load(addr, ty.size())?, ty);
+                let len = value.ty.size();
+                (Breakpoint::watchpoint(addr, len, kind), value)
+            }
+            (_, Kind::Watch) => {
+                let value = expr::evaluate(self, &expr)?;
+                (Breakpoint::watchpoint(0, 0, Kind::SoftwareWatch), value)
+            }
+            (Some(_), _) => bail!("Hardware watchpoints used exceeds limit."),
+            (None, _) => bail!(
+                "Expression cannot be implemented with read/access watchpoint."
+            ),
+        };
+        bp.what = text;
+        let num = self.add_breakpoint(bp);
+        let bp = &self.breakpoints[&num];
+        println!("{} {}: {}", watchpoint_title(bp.kind), num, bp.what);
+        self.watched.insert(num, Watched { expr, value });
+        Ok(num)
+    }
+
+    /// Check a debug register is left for another hardware breakpoint
+    fn hw_slot_free(&self) -> bool {
+        let used = self
+            .breakpoints
+            .values()
+            .filter(|bp| bp.enabled && !bp.pending && bp.kind.is_hardware())
+            .count();
+        used < hw::SLOTS
+    }
+
+    /// Set or remove a breakpoint's condition
+    pub fn condition_command(
+        &mut self,
+        num: usize,
+        cond: Option<String>,
+    ) -> Result<Option<Event>> {
+        let cond = check_condition(cond)?;
+        let bp = match self.breakpoints.get_mut(&num) {
+            Some(bp) => bp,
+            None => bail!("No breakpoint number {}.", num),
+        };
+        if cond.is_none() {
+            println!("Breakpoint {} now unconditional.", num);
+        }
+        bp.condition = cond;
+        Ok(None)
+    }
+
+    /// Read the command list a breakpoint runs when hit, by default the
+    /// last breakpoint set
+    pub fn commands_command(
+        &mut self,
+        num: Option<usize>,
+    ) -> Result<Option<Event>> {
+        let num = match num {
+            Some(num) => num,
+            None => match self.breakpoints.keys().last() {
+                Some(num) => *num,
+                None => bail!("No breakpoints specified."),
+            },
+        };
+        if !self.breakpoints.contains_key(&num) {
+            bail!("No breakpoint number {}.", num);
+        }
+        println!("Type commands for breakpoint(s) {}, one per line.", num);
+        println!("End with a line saying just \"end\".");
+        let lines = cli::read_command_list();
+        for line in lines.iter() {
+            cli::parse_command(line)?;
+        }
+        self.breakpoints[&num].commands = lines;
+        Ok(None)
+    }
+
+    /// Take the command lists queued by the last stop
+    pub fn take_stop_commands(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.stop_commands)
+    }
+
+    /// Set the number of hits a breakpoint ignores before stopping
+    pub fn ignore_command(
+        &mut self,
+        num: usize,
+        count: usize,
+    ) -> Result<Option<Event>> {
+        let bp = match self.breakpoints.get_mut(&num) {
+            Some(bp) => bp,
+            None => bail!("No breakpoint number {}.", num),
+        };
+        bp.ignore = count;
+        match count {
+            0 => println!("Will stop next time breakpoint {} is reached.", num),
+            1 => println!("Will ignore next crossing of breakpoint {}.", num),
+            n => println!(
+                "Will ignore next {} crossings of breakpoint {}.",
+                n, num
+            ),
+        }
+        Ok(None)
+    }
+
     pub fn handle_set_command(
         &mut self,
-        expr: Option<String>,
+        expr: Vec<String>,
         cmd: Option<Set>,
-    ) -> Result<Option<Event>> {
-        if expr.is_some() {
-            bail!("set expressions are not implemented yet");
+    ) -> Result<Option<Event>>
+    where
+        Self: Scope,
+    {
+        // Evaluate `set [var] EXPR` for its side effects
+        if !expr.is_empty() {
+            let expr = match expr[0].as_str() {
+                "var" | "variable" => &expr[1..],
+                _ => &expr[..],
+            };
+            expr::evaluate(self, &cli::parse_expr(&expr.join(" "))?)?;
+            return Ok(None);
         }
 
         match cmd {
             Some(Set::Args { args }) => self.set_args(args),
+            Some(Set::DisableRandomization { value }) => {
+                self.config.disable_randomization = value.unwrap_or(true);
+                Ok(None)
+            }
+            Some(Set::FollowForkMode { mode }) => {
+                self.config.follow_fork_mode = mode;
+                Ok(None)
+            }
+            Some(Set::DetachOnFork { value }) => {
+                self.config.detach_on_fork = value.unwrap_or(true);
+                Ok(None)
+            }
             None => Ok(None),
         }
     }
 }
 
+/// Resolve a location spec into a new breakpoint
+pub fn location_breakpoint<S: Scope>(
+    scope: &mut S,
+    loc: &Location,
+) -> Result<Breakpoint> {
+    let mut addr = location_address(scope, loc)?;
+    let bin = object(scope)?;
+    if let Location::Symbol { offset: 0, .. } = loc {
+        // Stop after the prologue when there is line info to find it
+        if let Some((_, range)) = bin.function_range(addr) {
+            addr = bin
+                .lines
+                .skip_prologue(range.start, range.end)
+                .unwrap_or(addr);
+        }
+    }
+    let mut bp = Breakpoint::new(addr);
+    bp.what = describe_location(bin, addr);
+    Ok(bp)
+}
+
+/// Symbolic location as entered, e.g. "main+4" or "hello.c:5"
+fn location_spec(loc: &Location) -> String {
+    match loc {
+        Location::Symbol { name, offset: 0 } => name.clone(),
+        Location::Symbol { name, offset } => format!("{}+{}", name, offset),
+        Location::Line { file, line } => format!("{}:{}", file, line),
+        Location::Address(_) => unreachable!("not a symbolic location"),
+    }
+}
+
+/// Location `start` runs to
+pub fn main_location() -> Location {
+    Location::Symbol {
+        name: "main".to_owned(),
+        offset: 0,
+    }
+}
+
+/// Insert enabled breakpoints into a newly started process
+pub fn arm_breakpoints(
+    breakpoints: &mut IndexMap<usize, Breakpoint>,
+    dbg: &mut Debugger,
+) -> Result<()> {
+    for bp in breakpoints.values_mut() {
+        bp.arm(dbg)?;
+    }
+    Ok(())
+}
+
+/// Check a breakpoint condition parses, keeping its text to evaluate on hits
+pub fn check_condition(cond: Option<String>) -> Result<Option<String>> {
+    if let Some(cond) = &cond {
+        cli::parse_expr(cond)?;
+    }
+    Ok(cond)
+}
+
+/// Object file of a context, required for symbols and source lines
+fn object<S: Scope>(scope: &S) -> Result<&Binary> {
+    match scope.symbols() {
+        Some(bin) => Ok(bin),
+        None => bail!("No symbol table is loaded.  Use the \"file\" command."),
+    }
+}
+
+/// Resolve a location spec into an address, evaluating address expressions
+/// in a context
+pub fn location_address<S: Scope>(
+    scope: &mut S,
+    loc: &Location,
+) -> Result<Address> {
+    if let Location::Address(expr) = loc {
+        return expr::evaluate(scope, expr)?.address();
+    }
+    let bin = object(scope)?;
+    let addr = match loc {
+        Location::Address(_) => unreachable!(),
+        Location::Symbol { name, offset } => match bin.symbol(name) {
+            Some(sym) => sym.addr + offset,
+            None => bail!("Function \"{}\" not defined.", name),
+        },
+        Location::Line { file, line } => {
+            match bin.lines.line_address(file, *line) {
+                Some(row) => row.addr,
+                None if bin.lines.has_file(file) => {
+                    bail!("No line {} in file \"{}\".", line, file)
+                }
+                None => bail!("No source file named {}.", file),
+            }
+        }
+    };
+    Ok(addr)
+}
+
+/// Describe an address by function and source line, e.g. "in main at
+/// hello.c:4", or by symbol offset, e.g. "<main+4>"
+pub fn describe_location(bin: &Binary, addr: Address) -> String {
+    match (bin.symbolize(addr), bin.source_line(addr)) {
+        (Some((sym, _)), Some((file, line))) => {
+            let file = file.file_name().unwrap_or_default();
+            format!("in {} at {}:{}", sym.name, file.to_string_lossy(), line)
+        }
+        _ => symbol_offset(bin, addr).unwrap_or_default(),
+    }
+}
+
+/// Symbolize an address as "<main+4>", or "<main>" at offset zero
+pub fn symbol_offset(bin: &Binary, addr: Address) -> Option<String> {
+    match bin.symbolize(addr)? {
+        (sym, 0) => Some(format!("<{}>", sym.name)),
+        (sym, offset) => Some(format!("<{}+{}>", sym.name, offset)),
+    }
+}
+
+/// Report a new breakpoint, e.g. "Breakpoint 1 at 0x1139: file hello.c, line
+/// 3."
+pub fn print_breakpoint(bin: &Binary, num: usize, bp: &Breakpoint) {
+    let title = breakpoint_title(bp);
+    match bin.source_line(bp.addr) {
+        Some((file, line)) => {
+            let file = file.file_name().unwrap_or_default();
+            println!(
+                "{} {} at {:#x}: file {}, line {}.",
+                title,
+                num,
+                bp.addr,
+                file.to_string_lossy(),
+                line
+            );
+        }
+        None => println!("{} {} at {:#x}", title, num, bp.addr),
+    }
+}
+
+/// Breakpoint kind as reported, e.g. "Temporary breakpoint"
+pub fn breakpoint_title(bp: &Breakpoint) -> &'static str {
+    match bp.kind {
+        Kind::Hardware => "Hardware assisted breakpoint",
+        _ if bp.temporary => "Temporary breakpoint",
+        _ => "Breakpoint",
+    }
+}
+
+/// Describe a stop location, gdb-style, e.g. "main () at hello.c:4" or
+/// "0x401000 in _start ()"
+pub fn frame_description(bin: &Binary, addr: Address) -> String {
+    let func = match bin.symbolize(addr) {
+        Some((sym, _)) => sym.name.as_str(),
+        None => "??",
+    };
+    match bin.source_line(addr) {
+        Some((file, line)) => {
+            let file = file.file_name().unwrap_or_default();
+            format!("{} () at {}:{}", func, file.to_string_lossy(), line)
+        }
+        None => format!("{:#x} in {} ()", addr, func),
+    }
+}
+
+/// Watchpoint kind as reported, e.g. "Hardware read watchpoint"
+pub fn watchpoint_title(kind: Kind) -> &'static str {
+    match kind {
+        Kind::ReadWatch => "Hardware read watchpoint",
+        Kind::AccessWatch => "Hardware access (read/write) watchpoint",
+        Kind::SoftwareWatch => "Watchpoint",
+        _ => "Hardware watchpoint",
+    }
+}
+
+/// Expressions without a file see only convenience variables
+impl Scope for Env<()> {
+    fn values(&mut self) -> &mut Values {
+        &mut self.values
+    }
+}
+
 /// Handle "environment only" commands when no file has been specified
 impl Env<()> {
     /// Build shortstop environment from command-line arguments
@@ -133,25 +757,81 @@ impl Env<()> {
             config: Config::new(opt),
             breakpoints: IndexMap::new(),
             next_breakpoint_id: 1,
+            locations: IndexMap::new(),
+            watched: IndexMap::new(),
+            stop_commands: Vec::new(),
             last_fmt: Default::default(),
             last_addr: None,
+            values: Default::default(),
         }
     }
 
     pub fn handle_command(&mut self, cmd: Cmd) -> Result<Option<Event>> {
         match cmd {
             Cmd::File { path } => self.set_file(path),
+            Cmd::Attach { pid } => self.attach_process(pid),
+            Cmd::Detach | Cmd::Kill => bail!("The program is not being run."),
+            Cmd::Thread { num } => self.thread_without_process(num),
             Cmd::Set { expr, cmd } => self.handle_set_command(expr, cmd),
+            Cmd::Print { fmt, expr } => self.print_command(fmt, expr),
             Cmd::Repeat => Ok(None),
+            Cmd::Break { loc, cond } => self.break_command(loc, cond, false),
+            Cmd::Tbreak { loc, cond } => self.break_command(loc, cond, true),
+            Cmd::Condition { num, cond } => self.condition_command(num, cond),
+            Cmd::Commands { num } => self.commands_command(num),
+            Cmd::Handle { args } => self.handle_signals_command(args),
+            Cmd::Info { cmd } => self.info_without_process(cmd),
+            Cmd::Ignore { num, count } => self.ignore_command(num, count),
+            Cmd::Delete { args } => self.delete_breakpoints(args),
+            Cmd::Disable { args } => self.enable_breakpoints(args, false),
+            Cmd::Enable { args } => self.enable_breakpoints(args, true),
             Cmd::Run { .. }
-            | Cmd::Break { .. }      // TODO
-            | Cmd::Delete { .. }     // TODO
-            | Cmd::Disable { .. }    // TODO
-            | Cmd::Enable { .. }     // TODO
+            | Cmd::Start { .. }
+            | Cmd::Starti { .. }
+            | Cmd::Hbreak { .. }
+            | Cmd::Watch { .. }
+            | Cmd::Rwatch { .. }
+            | Cmd::Awatch { .. }
             | Cmd::Continue { .. }
             | Cmd::Stepi { .. }
-            | Cmd::Examine { .. }
-            | Cmd::Info { .. } => bail!("No executable file specified."),
+            | Cmd::Disassemble { .. }
+            | Cmd::Examine { .. } => bail!("No executable file specified."),
         }
     }
+
+    /// Set a breakpoint at an address, or make one pending until a file is
+    /// loaded to resolve its location
+    fn break_command(
+        &mut self,
+        loc: Location,
+        cond: Option<String>,
+        temporary: bool,
+    ) -> Result<Option<Event>> {
+        let cond = check_condition(cond)?;
+        let spec = match &loc {
+            Location::Address(expr) => {
+                let addr = expr::evaluate(self, expr)?.address()?;
+                let mut bp = Breakpoint::new(addr);
+                bp.condition = cond;
+                bp.temporary = temporary;
+                let title = breakpoint_title(&bp);
+                let num = self.add_breakpoint(bp);
+                println!("{} {} at {:#x}", title, num, addr);
+                return Ok(None);
+            }
+            loc => location_spec(loc),
+        };
+        println!("No symbol table is loaded.  Use the \"file\" command.");
+        let prompt = "Make breakpoint pending on future shared library load?";
+        if cli::prompt_yes_no(prompt) {
+            let mut bp = Breakpoint::pending(spec);
+            bp.condition = cond;
+            bp.temporary = temporary;
+            let num = self.add_location_breakpoint(bp, &loc);
+            let bp = &self.breakpoints[&num];
+            let title = breakpoint_title(bp);
+            println!("{} {} ({}) pending.", title, num, bp.what);
+        }
+        Ok(None)
+    }
 }
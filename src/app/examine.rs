@@ -0,0 +1,422 @@
+use std::fmt;
+
+use failure::bail;
+
+use super::disasm::{self, Disassembler, Label};
+use super::expr::{self, Scope};
+use super::*;
+
+/// Longest x86 instruction encoding
+const MAX_INSN_LEN: usize = 15;
+
+/// gdb's default limit on printed string characters
+const MAX_STRING_LEN: usize = 200;
+
+/// Memory of an analysis context, as examined by x and disassemble
+pub trait Memory {
+    /// Object file for symbolizing addresses
+    fn object(&self) -> &Binary;
+    /// Read memory as the program sees it
+    fn read_memory(&mut self, addr: Address, size: usize) -> Result<Vec<u8>>;
+    /// Program counter of the selected frame, if there is one
+    fn current_pc(&mut self) -> Option<Address> {
+        None
+    }
+}
+
+/// Memory commands for contexts that have memory to examine
+impl<T> Env<T>
+where
+    Env<T>: Memory + Scope,
+{
+    pub fn examine_command(
+        &mut self,
+        fmt: Option<Fmt>,
+        addr: Option<Expr>,
+    ) -> Result<Option<Event>> {
+        // Update last used format and address
+        if let Some(fmt) = fmt {
+            self.set_fmt(fmt)?;
+        }
+        if let Some(addr) = addr {
+            let addr = expr::evaluate(self, &addr)?.address()?;
+            self.set_addr(addr)?;
+        }
+
+        let addr = match self.addr() {
+            Some(addr) => addr,
+            None => bail!("Argument required (starting display address)."),
+        };
+
+        let fmt = self.fmt();
+        let addr = examine(self, &fmt, addr)?;
+        self.set_addr(addr)
+    }
+
+    pub fn disassemble_command(
+        &mut self,
+        span: Option<Span>,
+    ) -> Result<Option<Event>> {
+        disassemble(self, span)?;
+        Ok(None)
+    }
+}
+
+/// Examine memory in a format, gdb-style, returning the address to continue
+/// from: following the last unit printed, or the first when walking backwards
+pub fn examine<M: Memory>(
+    mem: &mut M,
+    fmt: &Fmt,
+    addr: Address,
+) -> Result<Address> {
+    let count = fmt.repeat.unwrap_or(1) as usize;
+    let format = fmt.format.unwrap_or('x');
+
+    // Some formats imply a unit size
+    let size = match (format, fmt.size.unwrap_or('w')) {
+        ('a', _) if mem.object().is_64 => 8,
+        ('a', _) => 4,
+        ('c', _) | ('s', _) | ('i', _) => 1,
+        ('f', 'w') if fmt.size.is_some() => 4,
+        ('f', _) => 8,
+        (_, 'b') => 1,
+        (_, 'h') => 2,
+        (_, 'w') => 4,
+        (_, 'g') => 8,
+        _ => unreachable!(),
+    };
+
+    match (format, fmt.reverse) {
+        ('i', false) => examine_instructions(mem, addr, count),
+        ('i', true) => examine_instructions_back(mem, addr, count),
+        ('s', false) => examine_strings(mem, addr, count),
+        ('s', true) => examine_strings_back(mem, addr, count),
+        (_, false) => examine_units(mem, addr, count, size, format),
+        (_, true) => match addr.checked_sub(count * size) {
+            Some(start) => {
+                examine_units(mem, start, count, size, format)?;
+                Ok(start)
+            }
+            None => bail!("Cannot access memory at address {:#x}", addr),
+        },
+    }
+}
+
+/// Print fixed-size units, several per line
+fn examine_units<M: Memory>(
+    mem: &mut M,
+    addr: Address,
+    count: usize,
+    size: usize,
+    format: char,
+) -> Result<Address> {
+    let per_line = match size {
+        1 | 2 => 8,
+        4 => 4,
+        _ => 2,
+    };
+    let data = mem.read_memory(addr, count * size)?;
+    for (i, unit) in data.chunks(size).enumerate() {
+        if i % per_line == 0 {
+            if i > 0 {
+                println!();
+            }
+            print!("{}:", address_label(mem.object(), addr + i * size));
+        }
+        print!("\t{}", format_unit(Some(mem.object()), unit, format));
+    }
+    println!();
+    Ok(addr + count * size)
+}
+
+/// Format one little-endian unit of memory, symbolizing addresses when there
+/// is an object file
+pub fn format_unit(bin: Option<&Binary>, unit: &[u8], format: char) -> String {
+    let mut bytes = [0; 8];
+    bytes[..unit.len()].copy_from_slice(unit);
+    let value = u64::from_le_bytes(bytes);
+    let bits = unit.len() as u32 * 8;
+    let signed = (value << (64 - bits)) as i64 >> (64 - bits);
+
+    match (format, unit.len()) {
+        ('x', size) | ('z', size) => format!("0x{:0w$x}", value, w = size * 2),
+        ('o', _) if value == 0 => "0".to_owned(),
+        ('o', _) => format!("0{:o}", value),
+        ('u', _) => value.to_string(),
+        ('t', size) => format!("{:0w$b}", value, w = size * 8),
+        ('f', 4) => format_float(f32::from_bits(value as u32), 1e9),
+        ('f', 8) => format_float(f64::from_bits(value), 1e17),
+        ('a', _) => match bin
+            .and_then(|bin| env::symbol_offset(bin, value as Address))
+        {
+            Some(sym) => format!("{:#x} {}", value, sym),
+            None => format!("{:#x}", value),
+        },
+        ('c', _) => format!("{} '{}'", signed, escape(value as u8, '\'')),
+        _ => signed.to_string(),
+    }
+}
+
+/// Format a float like C's %g, switching to an exponent for small
+/// magnitudes and those beyond the type's significant digits
+fn format_float<F>(value: F, max: f64) -> String
+where
+    F: Copy + Into<f64> + fmt::Display + fmt::LowerExp,
+{
+    let magnitude = value.into().abs();
+    if magnitude != 0.0 && !(1e-4..max).contains(&magnitude) {
+        format!("{:e}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Escape a byte for a C character or string literal, as gdb prints them
+fn escape(byte: u8, quote: char) -> String {
+    match byte {
+        b'\n' => "\\n".to_owned(),
+        b'\t' => "\\t".to_owned(),
+        b'\r' => "\\r".to_owned(),
+        b'\\' => "\\\\".to_owned(),
+        b if b as char == quote => format!("\\{}", quote),
+        b if b == b' ' || b.is_ascii_graphic() => (b as char).to_string(),
+        b => format!("\\{:03o}", b),
+    }
+}
+
+/// Print NUL-terminated strings
+fn examine_strings<M: Memory>(
+    mem: &mut M,
+    mut addr: Address,
+    count: usize,
+) -> Result<Address> {
+    for _ in 0..count {
+        let label = address_label(mem.object(), addr);
+        let mut text = String::new();
+        let mut len = 0;
+        let terminated = loop {
+            if len == MAX_STRING_LEN {
+                break false;
+            }
+            match mem.read_memory(addr + len, 1)?[0] {
+                0 => break true,
+                byte => text.push_str(&escape(byte, '"')),
+            }
+            len += 1;
+        };
+        match terminated {
+            true => {
+                println!("{}:\t\"{}\"", label, text);
+                addr += len + 1;
+            }
+            false => {
+                println!("{}:\t\"{}\"...", label, text);
+                addr += len;
+            }
+        }
+    }
+    Ok(addr)
+}
+
+/// Print the strings preceding an address
+fn examine_strings_back<M: Memory>(
+    mem: &mut M,
+    addr: Address,
+    count: usize,
+) -> Result<Address> {
+    // Walk back over each string's terminator and then its characters
+    let mut start = addr;
+    for _ in 0..count {
+        if start == 0 {
+            break;
+        }
+        start -= 1;
+        let mut len = 0;
+        while start > 0 && len < MAX_STRING_LEN {
+            if mem.read_memory(start - 1, 1)?[0] == 0 {
+                break;
+            }
+            start -= 1;
+            len += 1;
+        }
+    }
+    examine_strings(mem, start, count)?;
+    Ok(start)
+}
+
+/// Print instructions, returning the address following the last one
+fn examine_instructions<M: Memory>(
+    mem: &mut M,
+    mut addr: Address,
+    count: usize,
+) -> Result<Address> {
+    let pc = mem.current_pc();
+    let mut dis = Disassembler::new(mem.object())?;
+    for _ in 0..count {
+        let code = read_code(mem, addr)?;
+        let insn = dis.decode(&code, addr)?;
+        insn.print(mem.object(), pc, Label::Symbol);
+        addr += insn.len;
+    }
+    Ok(addr)
+}
+
+/// Print the instructions preceding an address. x86 can't be decoded
+/// backwards, so decode forward from the start of the containing function.
+fn examine_instructions_back<M: Memory>(
+    mem: &mut M,
+    addr: Address,
+    count: usize,
+) -> Result<Address> {
+    let func = mem.object().function_range(addr.saturating_sub(1));
+    let start = match func {
+        Some((_, range)) => range.start,
+        None => bail!("No function contains the address {:#x}.", addr),
+    };
+
+    let mut dis = Disassembler::new(mem.object())?;
+    let mut starts = Vec::new();
+    let mut next = start;
+    while next < addr {
+        starts.push(next);
+        let code = read_code(mem, next)?;
+        next += dis.decode(&code, next)?.len;
+    }
+
+    let skip = starts.len().saturating_sub(count);
+    match starts.get(skip) {
+        Some(&first) => {
+            examine_instructions(mem, first, starts.len() - skip)?;
+            Ok(first)
+        }
+        None => Ok(addr),
+    }
+}
+
+/// Read enough memory to decode one instruction, without running past the
+/// end of a page that may be followed by unmapped memory
+fn read_code<M: Memory>(mem: &mut M, addr: Address) -> Result<Vec<u8>> {
+    match mem.read_memory(addr, MAX_INSN_LEN) {
+        Ok(code) => Ok(code),
+        Err(e) => {
+            let page_left = 0x1000 - (addr & 0xfff);
+            if page_left >= MAX_INSN_LEN {
+                return Err(e);
+            }
+            mem.read_memory(addr, page_left)
+        }
+    }
+}
+
+/// Address column label, e.g. "0x401126 <main>"
+fn address_label(bin: &Binary, addr: Address) -> String {
+    match env::symbol_offset(bin, addr) {
+        Some(sym) => format!("{:#x} {}", addr, sym),
+        None => format!("{:#x}", addr),
+    }
+}
+
+/// Disassemble a span of memory, by default the function around the pc
+pub fn disassemble<M: Memory + Scope>(
+    mem: &mut M,
+    span: Option<Span>,
+) -> Result<()> {
+    let pc = mem.current_pc();
+    let (range, func) = match span {
+        None => {
+            let pc = match pc {
+                Some(pc) => pc,
+                None => bail!("No frame selected."),
+            };
+            match mem.object().function_range(pc) {
+                Some((sym, range)) => (range, Some(sym.name.clone())),
+                None => bail!(
+                    "No function contains program counter for selected frame."
+                ),
+            }
+        }
+        Some(Span::Function(loc)) => {
+            let addr = env::location_address(mem, &loc)?;
+            match mem.object().function_range(addr) {
+                Some((sym, range)) => (range, Some(sym.name.clone())),
+                None => bail!("No function contains specified address."),
+            }
+        }
+        Some(Span::Range(start, end)) => {
+            let start = env::location_address(mem, &start)?;
+            let end = env::location_address(mem, &end)?;
+            (start..end, None)
+        }
+        Some(Span::Length(start, len)) => {
+            let start = env::location_address(mem, &start)?;
+            (start..start + len, None)
+        }
+    };
+
+    // Read past the end of the range to decode a final instruction
+    let code = mem
+        .read_memory(range.start, range.len() + MAX_INSN_LEN)
+        .or_else(|_| mem.read_memory(range.start, range.len()))?;
+    disasm::print_code(mem.object(), &code, range, func.as_deref(), pc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(value: i64, size: usize, format: char) -> String {
+        format_unit(None, &value.to_le_bytes()[..size], format)
+    }
+
+    #[test]
+    fn test_format_unit() {
+        assert_eq!(unit(0xff, 1, 'x'), "0xff");
+        assert_eq!(unit(0x12, 2, 'x'), "0x0012");
+        assert_eq!(unit(-1, 4, 'x'), "0xffffffff");
+        assert_eq!(unit(0x1234, 8, 'z'), "0x0000000000001234");
+        assert_eq!(unit(0, 4, 'o'), "0");
+        assert_eq!(unit(8, 1, 'o'), "010");
+        assert_eq!(unit(-1, 2, 'o'), "0177777");
+        assert_eq!(unit(-1, 1, 'd'), "-1");
+        assert_eq!(unit(-2, 2, 'd'), "-2");
+        assert_eq!(unit(-3, 4, 'd'), "-3");
+        assert_eq!(unit(i64::MIN, 8, 'd'), "-9223372036854775808");
+        assert_eq!(unit(-1, 1, 'u'), "255");
+        assert_eq!(unit(-1, 2, 'u'), "65535");
+        assert_eq!(unit(-1, 4, 'u'), "4294967295");
+        assert_eq!(unit(-1, 8, 'u'), "18446744073709551615");
+        assert_eq!(unit(5, 1, 't'), "00000101");
+        assert_eq!(unit(-1, 2, 't'), "1111111111111111");
+        assert_eq!(unit(0x401000, 8, 'a'), "0x401000");
+        assert_eq!(unit(65, 1, 'c'), "65 'A'");
+        assert_eq!(unit(-1, 1, 'c'), "-1 '\\377'");
+        assert_eq!(unit(10, 1, 'c'), "10 '\\n'");
+        assert_eq!(unit(39, 1, 'c'), "39 '\\''");
+    }
+
+    #[test]
+    fn test_format_float() {
+        let f = |value: f32| format_unit(None, &value.to_le_bytes(), 'f');
+        let g = |value: f64| format_unit(None, &value.to_le_bytes(), 'f');
+        assert_eq!(f(1.5), "1.5");
+        assert_eq!(f(-2.5), "-2.5");
+        assert_eq!(f(1e10), "1e10");
+        assert_eq!(g(0.0), "0");
+        assert_eq!(g(-0.25), "-0.25");
+        assert_eq!(g(1e-5), "1e-5");
+        assert_eq!(g(1e17), "1e17");
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape(b'a', '"'), "a");
+        assert_eq!(escape(b' ', '"'), " ");
+        assert_eq!(escape(b'"', '"'), "\\\"");
+        assert_eq!(escape(b'\'', '"'), "'");
+        assert_eq!(escape(b'\\', '"'), "\\\\");
+        assert_eq!(escape(b'\t', '"'), "\\t");
+        assert_eq!(escape(b'\r', '"'), "\\r");
+        assert_eq!(escape(0, '"'), "\\000");
+        assert_eq!(escape(0x80, '"'), "\\200");
+    }
+}
@@ -0,0 +1,576 @@
+use std::collections::HashMap;
+
+use failure::bail;
+
+use super::*;
+use crate::cli::{BinaryOp, Expr, Type, UnaryOp};
+use crate::dbg::regs;
+use crate::obj::SymbolKind;
+
+/// Evaluated expression value, the bits of an integer or pointer type
+/// zero-extended from the type's size
+#[derive(Debug, Clone, PartialEq)]
+pub struct Value {
+    pub bits: u64,
+    pub ty: Type,
+}
+
+impl Value {
+    /// Create a value, truncating bits to the type's size
+    pub fn new(bits: u64, ty: Type) -> Self {
+        let bits = match ty.size() {
+            8 => bits,
+            size => bits & ((1 << (size * 8)) - 1),
+        };
+        Value { bits, ty }
+    }
+
+    /// No value, e.g. of an unset convenience variable
+    pub fn void() -> Self {
+        Value {
+            bits: 0,
+            ty: Type::Void,
+        }
+    }
+
+    /// Typed like a C integer literal, int if it fits, otherwise long
+    fn literal(n: i64) -> Self {
+        match n as i32 as i64 == n {
+            true => Value::new(n as u64, Type::INT),
+            false => Value::new(n as u64, Type::LONG),
+        }
+    }
+
+    fn boolean(b: bool) -> Self {
+        Value::new(b as u64, Type::INT)
+    }
+
+    pub fn from_bytes(data: &[u8], ty: Type) -> Self {
+        let mut bytes = [0; 8];
+        bytes[..data.len()].copy_from_slice(data);
+        Value::new(u64::from_le_bytes(bytes), ty)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.bits.to_le_bytes()[..self.ty.size()].to_vec()
+    }
+
+    fn is_signed(&self) -> bool {
+        match self.ty {
+            Type::Int { signed, .. } => signed,
+            _ => false,
+        }
+    }
+
+    /// Value as a signed integer, sign-extended from its type's size
+    pub fn as_i64(&self) -> i64 {
+        match self.is_signed() {
+            true => {
+                let shift = 64 - self.ty.size() * 8;
+                (self.bits << shift) as i64 >> shift
+            }
+            false => self.bits as i64,
+        }
+    }
+
+    /// Convert to another type, like a C cast
+    fn cast(&self, ty: Type) -> Result<Self> {
+        if self.ty == Type::Void {
+            bail!("Invalid cast.");
+        }
+        Ok(Value::new(self.as_i64() as u64, ty))
+    }
+
+    /// Value used as an address, e.g. by x
+    pub fn address(&self) -> Result<Address> {
+        match self.ty {
+            Type::Int { .. } | Type::Pointer(_) => Ok(self.bits as Address),
+            _ => bail!("Value can't be converted to integer."),
+        }
+    }
+
+    /// Type pointed to, for dereferencing
+    fn target(&self) -> Result<Type> {
+        match &self.ty {
+            Type::Pointer(ty) if **ty != Type::Void => Ok((**ty).clone()),
+            _ => bail!("Attempt to take contents of a non-pointer value."),
+        }
+    }
+}
+
+/// Convenience variables and the value history, $1, $2, ...
+#[derive(Debug, Default)]
+pub struct Values {
+    vars: HashMap<String, Value>,
+    history: Vec<Value>,
+}
+
+impl Values {
+    /// Record a printed value, returning its history number
+    pub fn record(&mut self, value: Value) -> usize {
+        self.history.push(value);
+        self.history.len()
+    }
+}
+
+/// What expressions can see of an analysis context
+pub trait Scope {
+    /// Object file for symbols, if one is loaded
+    fn symbols(&self) -> Option<&Binary> {
+        None
+    }
+    /// Convenience variables and value history
+    fn values(&mut self) -> &mut Values;
+    /// Read memory as the program sees it
+    fn load(&mut self, addr: Address, _size: usize) -> Result<Vec<u8>> {
+        bail!("Cannot access memory at address {:#x}", addr)
+    }
+    /// Write memory of the program
+    fn store(&mut self, addr: Address, _data: &[u8]) -> Result<()> {
+        bail!("Cannot access memory at address {:#x}", addr)
+    }
+    /// Read a register by name
+    fn register(&mut self, _name: &str) -> Result<u64> {
+        bail!("No registers.")
+    }
+    /// Write a register by name
+    fn set_register(&mut self, _name: &str, _value: u64) -> Result<()> {
+        bail!("No registers.")
+    }
+}
+
+/// Expression commands for every context
+impl<T> Env<T>
+where
+    Env<T>: Scope,
+{
+    pub fn print_command(
+        &mut self,
+        fmt: Option<Fmt>,
+        expr: Option<Expr>,
+    ) -> Result<Option<Event>> {
+        let fmt = fmt.unwrap_or_default();
+        if fmt.repeat.is_some() {
+            bail!(
+                "Item count other than 1 is meaningless in \"print\" command."
+            );
+        }
+        if fmt.size.is_some() {
+            bail!("Size letters are meaningless in \"print\" command.");
+        }
+        if let Some(c @ 'i') | Some(c @ 's') = fmt.format {
+            bail!(
+                "Format letter \"{}\" is meaningless in \"print\" command.",
+                c
+            );
+        }
+
+        // Without an expression, show the last value again
+        let expr = expr.unwrap_or_else(|| Expr::Dollar(String::new()));
+        let value = evaluate(self, &expr)?;
+        let text = format_value(self.symbols(), &value, fmt.format);
+        let num = self.values().record(value);
+        println!("${} = {}", num, text);
+        Ok(None)
+    }
+}
+
+/// Type of a register's value, e.g. a code pointer for rip
+fn register_type(name: &str) -> Type {
+    match name {
+        "rip" => Type::Pointer(Box::new(Type::Function)),
+        "rsp" | "rbp" => Type::Pointer(Box::new(Type::Void)),
+        "eflags" | "cs" | "ss" | "ds" | "es" | "fs" | "gs" => Type::INT,
+        _ => Type::LONG,
+    }
+}
+
+/// Register named by a `$NAME`, if it is one
+fn register_name(name: &str) -> Option<&str> {
+    let name = regs::alias(name);
+    regs::GENERAL.iter().find(|&&reg| reg == name).cloned()
+}
+
+/// Evaluate an expression in a context
+pub fn evaluate<S: Scope>(scope: &mut S, expr: &Expr) -> Result<Value> {
+    let value = match expr {
+        Expr::Int(n) => Value::literal(*n),
+        Expr::Dollar(name) => dollar(scope, name)?,
+        Expr::Symbol(name) => symbol(scope, name)?,
+        Expr::Unary(op, expr) => unary(scope, *op, expr)?,
+        Expr::Binary(BinaryOp::And, lhs, rhs) => {
+            let lhs = evaluate(scope, lhs)?.bits != 0;
+            Value::boolean(lhs && evaluate(scope, rhs)?.bits != 0)
+        }
+        Expr::Binary(BinaryOp::Or, lhs, rhs) => {
+            let lhs = evaluate(scope, lhs)?.bits != 0;
+            Value::boolean(lhs || evaluate(scope, rhs)?.bits != 0)
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let lhs = evaluate(scope, lhs)?;
+            let rhs = evaluate(scope, rhs)?;
+            binary(*op, lhs, rhs)?
+        }
+        Expr::Cast(ty, expr) => evaluate(scope, expr)?.cast(ty.clone())?,
+        Expr::Memory(ty, addr) => {
+            let addr = evaluate(scope, addr)?.address()?;
+            Value::from_bytes(&scope.load(addr, ty.size())?, ty.clone())
+        }
+        Expr::Assign(lhs, rhs) => {
+            let value = evaluate(scope, rhs)?;
+            assign(scope, lhs, value)?
+        }
+    };
+    Ok(value)
+}
+
+/// Registers, convenience variables and value history
+fn dollar<S: Scope>(scope: &mut S, name: &str) -> Result<Value> {
+    if let Some(reg) = register_name(name) {
+        return Ok(Value::new(scope.register(reg)?, register_type(reg)));
+    }
+    let values = scope.values();
+    let back = |n: usize| match values.history.len().checked_sub(n) {
+        Some(i) => values.history[i].clone(),
+        None => Value::void(),
+    };
+    let value = match name {
+        "" => back(1),
+        "$" => back(2),
+        name if name.chars().all(|c| c.is_ascii_digit()) => {
+            let num: usize = name.parse()?;
+            match values.history.get(num.wrapping_sub(1)) {
+                Some(value) => value.clone(),
+                None => bail!("History has not yet reached ${}.", num),
+            }
+        }
+        name => match values.vars.get(name) {
+            Some(value) => value.clone(),
+            None => Value::void(),
+        },
+    };
+    Ok(value)
+}
+
+/// Symbols evaluate to their address, there are no debug info types
+fn symbol<S: Scope>(scope: &mut S, name: &str) -> Result<Value> {
+    let bin = match scope.symbols() {
+        Some(bin) => bin,
+        None => bail!("No symbol table is loaded.  Use the \"file\" command."),
+    };
+    match bin.symbol(name) {
+        Some(sym) => {
+            let ty = match sym.kind {
+                SymbolKind::Function => Type::Function,
+                _ => Type::Void,
+            };
+            Ok(Value::new(sym.addr as u64, Type::Pointer(Box::new(ty))))
+        }
+        None => bail!("No symbol \"{}\" in current context.", name),
+    }
+}
+
+fn unary<S: Scope>(scope: &mut S, op: UnaryOp, expr: &Expr) -> Result<Value> {
+    if op == UnaryOp::AddrOf {
+        return address_of(scope, expr);
+    }
+    let value = evaluate(scope, expr)?;
+    let value = match op {
+        UnaryOp::Deref => {
+            let ty = value.target()?;
+            let data = scope.load(value.address()?, ty.size())?;
+            Value::from_bytes(&data, ty)
+        }
+        UnaryOp::Not => Value::boolean(value.bits == 0),
+        UnaryOp::Neg | UnaryOp::BitNot => {
+            let ty = promote(&number(&value)?.ty, &Type::INT);
+            let n = value.as_i64();
+            match op {
+                UnaryOp::Neg => Value::new(n.wrapping_neg() as u64, ty),
+                _ => Value::new(!n as u64, ty),
+            }
+        }
+        UnaryOp::AddrOf => unreachable!(),
+    };
+    Ok(value)
+}
+
+/// Address of an lvalue in memory
+fn address_of<S: Scope>(scope: &mut S, expr: &Expr) -> Result<Value> {
+    match expr {
+        Expr::Symbol(name) => symbol(scope, name),
+        Expr::Unary(UnaryOp::Deref, ptr) => {
+            let ptr = evaluate(scope, ptr)?;
+            ptr.target()?;
+            Ok(ptr)
+        }
+        Expr::Memory(ty, addr) => {
+            let addr = evaluate(scope, addr)?.address()?;
+            Ok(Value::new(addr as u64, Type::Pointer(Box::new(ty.clone()))))
+        }
+        _ => bail!("Attempt to take address of value not located in memory."),
+    }
+}
+
+/// Check a value can take part in arithmetic
+fn number(value: &Value) -> Result<&Value> {
+    match value.ty {
+        Type::Int { .. } => Ok(value),
+        _ => bail!("Argument to arithmetic operation not a number or boolean."),
+    }
+}
+
+/// Common integer type of two operands, like C's usual arithmetic
+/// conversions: at least int, unsigned if the widest operand is
+fn promote(lhs: &Type, rhs: &Type) -> Type {
+    let size = *[lhs.size(), rhs.size(), 4].iter().max().unwrap();
+    let unsigned = |ty: &Type| match ty {
+        Type::Int { size: s, signed } => *s == size && !signed,
+        _ => false,
+    };
+    Type::Int {
+        size,
+        signed: !unsigned(lhs) && !unsigned(rhs),
+    }
+}
+
+fn binary(op: BinaryOp, lhs: Value, rhs: Value) -> Result<Value> {
+    use BinaryOp::*;
+
+    // Pointer arithmetic, scaled by the size of the type pointed to
+    match (&lhs.ty, &rhs.ty, op) {
+        (Type::Pointer(ty), Type::Int { .. }, Add)
+        | (Type::Pointer(ty), Type::Int { .. }, Sub) => {
+            let offset = rhs.as_i64().wrapping_mul(ty.size() as i64);
+            let bits = match op {
+                Add => lhs.bits.wrapping_add(offset as u64),
+                _ => lhs.bits.wrapping_sub(offset as u64),
+            };
+            return Ok(Value::new(bits, lhs.ty.clone()));
+        }
+        (Type::Int { .. }, Type::Pointer(_), Add) => {
+            return binary(op, rhs, lhs);
+        }
+        (Type::Pointer(ty), Type::Pointer(_), Sub) => {
+            let diff = lhs.bits.wrapping_sub(rhs.bits) as i64;
+            return Ok(Value::new(
+                (diff / ty.size() as i64) as u64,
+                Type::LONG,
+            ));
+        }
+        (Type::Pointer(_), Type::Pointer(_), _)
+        | (Type::Pointer(_), Type::Int { .. }, _)
+        | (Type::Int { .. }, Type::Pointer(_), _)
+            if is_comparison(op) =>
+        {
+            return Ok(compare(op, lhs.bits.cmp(&rhs.bits)));
+        }
+        _ => (),
+    }
+
+    let ty = promote(&number(&lhs)?.ty, &number(&rhs)?.ty);
+    let signed = match ty {
+        Type::Int { signed, .. } => signed,
+        _ => unreachable!(),
+    };
+    let (a, b) = (lhs.as_i64(), rhs.as_i64());
+    if is_comparison(op) {
+        let ordering = match signed {
+            true => a.cmp(&b),
+            false => (a as u64).cmp(&(b as u64)),
+        };
+        return Ok(compare(op, ordering));
+    }
+    if (op == Div || op == Rem) && b == 0 {
+        bail!("Division by zero");
+    }
+    let bits = match (op, signed) {
+        (Add, _) => a.wrapping_add(b) as u64,
+        (Sub, _) => a.wrapping_sub(b) as u64,
+        (Mul, _) => a.wrapping_mul(b) as u64,
+        (Div, true) => a.wrapping_div(b) as u64,
+        (Div, false) => a as u64 / b as u64,
+        (Rem, true) => a.wrapping_rem(b) as u64,
+        (Rem, false) => a as u64 % b as u64,
+        (Shl, _) => (a as u64).wrapping_shl(b as u32),
+        (Shr, true) => a.wrapping_shr(b as u32) as u64,
+        (Shr, false) => (a as u64).wrapping_shr(b as u32),
+        (BitAnd, _) => (a & b) as u64,
+        (BitXor, _) => (a ^ b) as u64,
+        (BitOr, _) => (a | b) as u64,
+        _ => unreachable!(),
+    };
+    // Results are computed in 64 bits and truncated to the common type
+    Ok(Value::new(bits, ty))
+}
+
+fn is_comparison(op: BinaryOp) -> bool {
+    use BinaryOp::*;
+    matches!(op, Lt | Le | Gt | Ge | Eq | Ne)
+}
+
+fn compare(op: BinaryOp, ordering: std::cmp::Ordering) -> Value {
+    use std::cmp::Ordering::*;
+    use BinaryOp::*;
+    Value::boolean(match op {
+        Lt => ordering == Less,
+        Le => ordering != Greater,
+        Gt => ordering == Greater,
+        Ge => ordering != Less,
+        Eq => ordering == Equal,
+        _ => ordering != Equal,
+    })
+}
+
+/// Assign to a register, convenience variable or memory
+fn assign<S: Scope>(scope: &mut S, lhs: &Expr, value: Value) -> Result<Value> {
+    let (addr, ty) = match lhs {
+        Expr::Dollar(name) if name.is_empty() || name == "$" => {
+            bail!("Left operand of assignment is not a modifiable lvalue.")
+        }
+        Expr::Dollar(name) if name.chars().all(|c| c.is_ascii_digit()) => {
+            bail!("Left operand of assignment is not a modifiable lvalue.")
+        }
+        Expr::Dollar(name) => match register_name(name) {
+            Some(reg) => {
+                let value = value.cast(register_type(reg))?;
+                scope.set_register(reg, value.bits)?;
+                return Ok(value);
+            }
+            None => {
+                scope.values().vars.insert(name.clone(), value.clone());
+                return Ok(value);
+            }
+        },
+        lhs => match memory_lvalue(scope, lhs)? {
+            Some(lvalue) => lvalue,
+            None => bail!("Left operand of assignment is not an lvalue."),
+        },
+    };
+    let value = value.cast(ty)?;
+    scope.store(addr, &value.to_bytes())?;
+    Ok(value)
+}
+
+/// Check an expression's value can never change, e.g. `5` or `-(1 + 2)`
+pub fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Int(_) => true,
+        Expr::Unary(UnaryOp::Deref, _) => false,
+        Expr::Unary(_, expr) | Expr::Cast(_, expr) => is_constant(expr),
+        Expr::Binary(_, lhs, rhs) => is_constant(lhs) && is_constant(rhs),
+        _ => false,
+    }
+}
+
+/// Address and type of an lvalue in memory, `*PTR` or `{TYPE} ADDR`, or None
+/// for other expressions
+pub fn memory_lvalue<S: Scope>(
+    scope: &mut S,
+    expr: &Expr,
+) -> Result<Option<(Address, Type)>> {
+    let lvalue = match expr {
+        Expr::Symbol(name) => {
+            bail!("'{}' has unknown type; cast it to its declared type", name)
+        }
+        Expr::Unary(UnaryOp::Deref, ptr) => {
+            let ptr = evaluate(scope, ptr)?;
+            (ptr.address()?, ptr.target()?)
+        }
+        Expr::Memory(ty, addr) => {
+            (evaluate(scope, addr)?.address()?, ty.clone())
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(lvalue))
+}
+
+/// Format a value for print, gdb-style, e.g. "42", "(void *) 0x7ffe0" or
+/// "(void (*)()) 0x401126 <main+4>"
+pub fn format_value(
+    bin: Option<&Binary>,
+    value: &Value,
+    format: Option<char>,
+) -> String {
+    let symbol = |addr: u64| {
+        bin.and_then(|bin| env::symbol_offset(bin, addr as Address))
+            .map(|sym| format!(" {}", sym))
+            .unwrap_or_default()
+    };
+    match (format, &value.ty) {
+        (_, Type::Void) => "void".to_owned(),
+        (None, Type::Pointer(_)) => {
+            format!("({}) {:#x}{}", value.ty, value.bits, symbol(value.bits))
+        }
+        (None, Type::Int { size: 1, .. }) => {
+            examine::format_unit(bin, &value.to_bytes(), 'c')
+        }
+        (None, _) if value.is_signed() => value.as_i64().to_string(),
+        (None, _) => value.bits.to_string(),
+        // Unlike x, print doesn't pad hex and binary to the value's size
+        (Some('x'), _) => format!("{:#x}", value.bits),
+        (Some('t'), _) => format!("{:b}", value.bits),
+        (Some('f'), Type::Int { .. }) if value.is_signed() => {
+            value.as_i64().to_string()
+        }
+        (Some('f'), Type::Int { .. }) => value.bits.to_string(),
+        (Some(format), _) => {
+            examine::format_unit(bin, &value.to_bytes(), format)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::parse_expr;
+
+    /// Scope with 16 bytes of memory at 0x1000 and no registers
+    #[derive(Default)]
+    struct TestScope {
+        values: Values,
+        memory: Vec<u8>,
+    }
+
+    impl Scope for TestScope {
+        fn values(&mut self) -> &mut Values {
+            &mut self.values
+        }
+
+        fn load(&mut self, addr: Address, size: usize) -> Result<Vec<u8>> {
+            Ok(self.memory[addr - 0x1000..addr - 0x1000 + size].to_vec())
+        }
+
+        fn store(&mut self, addr: Address, data: &[u8]) -> Result<()> {
+            let start = addr - 0x1000;
+            self.memory[start..start + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+    }
+
+    fn eval(scope: &mut TestScope, expr: &str) -> Result<Value> {
+        evaluate(scope, &parse_expr(expr)?)
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let mut scope = TestScope {
+            memory: vec![0; 16],
+            ..Default::default()
+        };
+        assert_eq!(eval(&mut scope, "1 + 2 * 3").unwrap().as_i64(), 7);
+        assert_eq!(eval(&mut scope, "-7 / 2").unwrap().as_i64(), -3);
+        assert_eq!(eval(&mut scope, "0xffffffff + 1").unwrap().bits, 1 << 32);
+        assert_eq!(eval(&mut scope, "(char)0x1ff").unwrap().as_i64(), -1);
+        assert_eq!(eval(&mut scope, "3 > 2 && 1 != 1").unwrap().bits, 0);
+        assert!(eval(&mut scope, "1 / 0").is_err());
+
+        eval(&mut scope, "$x = 0x1000").unwrap();
+        eval(&mut scope, "{int}($x + 4) = -2").unwrap();
+        assert_eq!(eval(&mut scope, "*((int *)$x + 1)").unwrap().as_i64(), -2);
+        assert_eq!(eval(&mut scope, "{long}0x1000").unwrap().bits, !1 << 32);
+        assert_eq!(eval(&mut scope, "$unset").unwrap().ty, Type::Void);
+        assert!(eval(&mut scope, "*$x").is_err());
+        assert!(eval(&mut scope, "$rax").is_err());
+    }
+}
@@ -1,14 +1,18 @@
-use failure::Error;
+use failure::{bail, Error};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-use crate::cli::{self, Cmd, Fmt, Opt, Set};
-use crate::dbg::{Address, Breakpoint, Debugger, Event as DebugEvent};
+use crate::cli::{self, Cmd, Expr, Fmt, Location, Opt, Set, Span};
+use crate::dbg::{Address, Breakpoint, Debugger, Event as DebugEvent, Pid};
 use crate::obj::Binary;
 
 mod bin;
 mod dbg;
+mod disasm;
 mod env;
+mod examine;
+mod expr;
+mod signal;
 use env::Env;
 
 /// Application contexts for command execution, for configuration before a
@@ -26,6 +30,8 @@ pub enum Context {
 pub enum Event {
     Open(Binary),
     Run(Debugger),
+    Start(Debugger),
+    Attach(Binary, Pid),
     Process(DebugEvent),
 }
 
@@ -33,21 +39,68 @@ pub enum Event {
 #[derive(Debug)]
 pub struct Shortstop {
     ctx: Option<Context>,
+    // Command to run for an empty line
+    repeat: Option<Cmd>,
 }
 
 impl Shortstop {
     pub fn new(opt: &Opt) -> Self {
         Shortstop {
             ctx: Some(Context::Env(Env::new(opt))),
+            repeat: None,
         }
     }
 
     pub fn handle_command_line(&mut self, line: String) -> Result<()> {
         let cmd = cli::parse_command(&line)?;
-        self.handle_command(cmd)
+        self.handle_command(cmd)?;
+        self.run_stop_commands()
+    }
+
+    /// Run the command lists of the breakpoints the program stopped at. A
+    /// command resuming the program ends its list, gdb-style, and the lists
+    /// of breakpoints it stops at run next.
+    fn run_stop_commands(&mut self) -> Result<()> {
+        // An empty line still repeats the command typed last
+        let repeat = self.repeat.clone();
+        let result = self.run_stop_command_lists();
+        self.repeat = repeat;
+        result
+    }
+
+    fn run_stop_command_lists(&mut self) -> Result<()> {
+        loop {
+            let lines = match &mut self.ctx {
+                Some(Context::Debug(dbg)) => dbg.take_stop_commands(),
+                _ => return Ok(()),
+            };
+            if lines.is_empty() {
+                return Ok(());
+            }
+            for line in lines {
+                let cmd = cli::parse_command(&line)?;
+                let resumes = cmd.resumes();
+                self.handle_command(cmd)?;
+                if resumes {
+                    break;
+                }
+            }
+        }
     }
 
     pub fn handle_command(&mut self, cmd: Cmd) -> Result<()> {
+        // Repeat the last repeatable command on an empty line
+        let cmd = match cmd {
+            Cmd::Repeat => match self.repeat.clone() {
+                Some(cmd) => cmd,
+                None => return Ok(()),
+            },
+            cmd => {
+                self.repeat = cmd.repeat();
+                cmd
+            }
+        };
+
         let ctx = self.ctx.take();
 
         // Context-sensitive command dispatch
@@ -80,42 +133,147 @@ impl Shortstop {
         let ctx = match (ctx, event) {
             // Env --[ file ]--> Static
             (Context::Env(env), Some(Event::Open(bin))) => {
-                Context::Static(env.into_binary(bin))
+                let mut bin = env.into_binary(bin);
+                bin.resolve_breakpoints();
+                Context::Static(bin)
             }
             // Static --[ file ]--> Static
             (Context::Static(oldbin), Some(Event::Open(newbin))) => {
-                Context::Static(oldbin.into_binary(newbin))
+                let mut newbin = oldbin.into_binary(newbin);
+                newbin.resolve_breakpoints();
+                Context::Static(newbin)
             }
             // Static --[ run ]--> Debug
             (Context::Static(bin), Some(Event::Run(dbg))) => {
-                Context::Debug(bin.into_debugger(dbg))
+                let mut dbg = bin.into_debugger(dbg);
+                if let Err(e) = dbg.refresh_watched() {
+                    self.ctx.replace(Context::Debug(dbg));
+                    return Err(e);
+                }
+                Context::Debug(dbg)
             }
             // Debug --[ file ]--> Static
             (Context::Debug(dbg), Some(Event::Open(bin))) => {
-                Context::Static(dbg.into_binary(bin))
-            }
-            (Context::Debug(dbg), Some(Event::Process(event))) => match event {
-                DebugEvent::Exited(pid, status) => {
-                    let status = match status {
-                        0 => format!("normally"),
-                        _ => format!("with code {}", status),
-                    };
-                    println!(
-                        "[Inferior 1 (process {}) exited {}]",
-                        pid, status
-                    );
-                    Context::Debug(dbg) // TODO: static ctx from exited process
+                let bias = dbg.binary().bias;
+                let mut bin = dbg.into_binary(bin);
+                bin.relocate_breakpoints(bias, bin.inner.bias);
+                bin.resolve_breakpoints();
+                Context::Static(bin)
+            }
+            // Static --[ start ]--> Debug, then run to main
+            (Context::Static(bin), Some(Event::Start(dbg))) => {
+                let mut dbg = bin.into_debugger(dbg);
+                match dbg.refresh_watched().and_then(|_| dbg.resume(1)) {
+                    Ok(Some(Event::Process(event))) => {
+                        process_event(dbg, event)
+                    }
+                    Ok(_) => Context::Debug(dbg),
+                    Err(e) => {
+                        self.ctx.replace(Context::Debug(dbg));
+                        return Err(e);
+                    }
                 }
-                event => {
-                    dbg!(event);
-                    Context::Debug(dbg)
+            }
+            (Context::Debug(dbg), Some(Event::Process(event))) => {
+                process_event(dbg, event)
+            }
+            // Env|Static|Debug --[ attach ]--> Debug
+            (ctx, Some(Event::Attach(bin, pid))) => {
+                let mut bin = match ctx {
+                    Context::Env(env) => env.into_binary(bin),
+                    Context::Static(old) => {
+                        let bias = old.inner.bias;
+                        let mut bin = old.into_binary(bin);
+                        bin.relocate_breakpoints(bias, bin.inner.bias);
+                        bin
+                    }
+                    Context::Debug(dbg) => {
+                        let bias = dbg.binary().bias;
+                        let mut bin = dbg.into_binary(bin);
+                        bin.relocate_breakpoints(bias, bin.inner.bias);
+                        bin
+                    }
+                };
+                bin.resolve_breakpoints();
+                let dbg = match bin.attach(pid) {
+                    Ok(dbg) => dbg,
+                    Err(e) => {
+                        self.ctx.replace(Context::Static(bin));
+                        return Err(e);
+                    }
+                };
+                let mut dbg = bin.into_debugger(dbg);
+                if let Err(e) = dbg.refresh_watched() {
+                    self.ctx.replace(Context::Debug(dbg));
+                    return Err(e);
                 }
-            },
+                Context::Debug(dbg)
+            }
             (ctx, None) => ctx,
-            _ => panic!("unhandled application event"),
+            // A command returned an event its context has no transition for
+            (ctx, Some(_)) => {
+                self.ctx.replace(ctx);
+                bail!("unhandled application event");
+            }
         };
 
         self.ctx.replace(ctx);
         Ok(())
     }
 }
+
+/// Let go of a debugged process on quit, leaving one attached to running
+/// without breakpoints
+impl Drop for Shortstop {
+    fn drop(&mut self) {
+        if let Some(Context::Debug(dbg)) = self.ctx.as_mut() {
+            if let Err(e) = dbg.inner.release() {
+                eprintln!("error: {}", e);
+            }
+        }
+    }
+}
+
+/// Report a debugged process event
+fn process_event(dbg: Env<Debugger>, event: DebugEvent) -> Context {
+    match event {
+        DebugEvent::Exited(pid, status) => {
+            let status = match status {
+                0 => "normally".to_owned(),
+                _ => format!("with code {}", status),
+            };
+            println!("[Inferior 1 (process {}) exited {}]", pid, status);
+            // Debug --[ exited ]--> Static
+            Context::Static(dbg.map_context(Debugger::into_binary))
+        }
+        DebugEvent::Detached(pid) => {
+            println!("[Inferior 1 (process {}) detached]", pid);
+            // Debug --[ detach ]--> Static
+            Context::Static(dbg.map_context(Debugger::into_binary))
+        }
+        DebugEvent::Killed(pid) => {
+            println!("[Inferior 1 (process {}) killed]", pid);
+            // Debug --[ kill ]--> Static
+            Context::Static(dbg.map_context(Debugger::into_binary))
+        }
+        DebugEvent::Terminated(pid, signal, core) => {
+            let core = match core {
+                true => " (core dumped)",
+                false => "",
+            };
+            println!(
+                "[Inferior 1 (process {}) terminated with signal {}{}]",
+                pid, signal, core
+            );
+            // Debug --[ killed ]--> Static
+            Context::Static(dbg.map_context(Debugger::into_binary))
+        }
+        // Stops are reported as they are handled, and the process is still
+        // being debugged
+        DebugEvent::Stopped
+        | DebugEvent::Received(_)
+        | DebugEvent::Forked(..)
+        | DebugEvent::Exec
+        | DebugEvent::VforkDone => Context::Debug(dbg),
+    }
+}
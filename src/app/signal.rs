@@ -0,0 +1,223 @@
+use std::str::FromStr;
+
+use failure::bail;
+use indexmap::IndexMap;
+
+use super::Result;
+use crate::dbg::Signal;
+
+/// What to do when the program receives a signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    /// Stop the program
+    pub stop: bool,
+    /// Report the signal
+    pub print: bool,
+    /// Deliver the signal when the program resumes
+    pub pass: bool,
+}
+
+impl Policy {
+    /// gdb's defaults, quietly passing signals programs expect routinely,
+    /// and keeping the ones the debugger uses itself
+    fn new(signal: Signal) -> Self {
+        match signal {
+            Signal::SIGALRM
+            | Signal::SIGURG
+            | Signal::SIGCHLD
+            | Signal::SIGWINCH
+            | Signal::SIGIO
+            | Signal::SIGVTALRM
+            | Signal::SIGPROF => Policy {
+                stop: false,
+                print: false,
+                pass: true,
+            },
+            Signal::SIGINT | Signal::SIGTRAP => Policy {
+                stop: true,
+                print: true,
+                pass: false,
+            },
+            _ => Policy {
+                stop: true,
+                print: true,
+                pass: true,
+            },
+        }
+    }
+}
+
+/// Signal handling policies, set with `handle`
+#[derive(Debug, Clone)]
+pub struct Signals {
+    policies: IndexMap<Signal, Policy>,
+}
+
+impl Default for Signals {
+    fn default() -> Self {
+        Signals {
+            policies: Signal::iterator().map(|s| (s, Policy::new(s))).collect(),
+        }
+    }
+}
+
+impl Signals {
+    pub fn get(&self, signal: Signal) -> Policy {
+        self.policies[&signal]
+    }
+
+    /// Apply `handle` arguments, signals and the actions to take for them,
+    /// returning the signals changed
+    pub fn handle(&mut self, args: &[String]) -> Result<Vec<Signal>> {
+        let mut signals = Vec::new();
+        let mut actions = Vec::new();
+        for arg in args {
+            match arg.as_str() {
+                "stop" | "nostop" | "print" | "noprint" | "pass" | "nopass"
+                | "ignore" | "noignore" => actions.push(arg.as_str()),
+                // Every signal but those the debugger uses itself
+                "all" => signals.extend(
+                    Signal::iterator()
+                        .filter(|s| *s != Signal::SIGTRAP)
+                        .filter(|s| *s != Signal::SIGINT),
+                ),
+                arg => match parse_signal(arg) {
+                    Some(signal) => signals.push(signal),
+                    None => bail!(
+                        "Unrecognized or ambiguous flag word: \"{}\".",
+                        arg
+                    ),
+                },
+            }
+        }
+        if signals.is_empty() {
+            bail!("Argument required (signal to handle).");
+        }
+        for signal in signals.iter() {
+            let policy = self.policies.get_mut(signal).expect("signal");
+            for action in actions.iter() {
+                match *action {
+                    "stop" => {
+                        policy.stop = true;
+                        policy.print = true;
+                    }
+                    "nostop" => policy.stop = false,
+                    "print" => policy.print = true,
+                    "noprint" => {
+                        policy.print = false;
+                        policy.stop = false;
+                    }
+                    "pass" | "noignore" => policy.pass = true,
+                    _ => policy.pass = false,
+                }
+            }
+        }
+        Ok(signals)
+    }
+
+    /// Print policies, gdb-style, for all signals or just those given
+    pub fn print(&self, signals: &[Signal]) {
+        println!("Signal        Stop\tPrint\tPass to program\tDescription");
+        if signals.is_empty() {
+            println!();
+        }
+        let yes_no = |b| match b {
+            true => "Yes",
+            false => "No",
+        };
+        for (signal, policy) in self.policies.iter() {
+            if !signals.is_empty() && !signals.contains(signal) {
+                continue;
+            }
+            println!(
+                "{:<14}{}\t{}\t{}\t\t{}",
+                signal.as_ref(),
+                yes_no(policy.stop),
+                yes_no(policy.print),
+                yes_no(policy.pass),
+                description(*signal)
+            );
+        }
+        if signals.is_empty() {
+            println!();
+            println!("Use the \"handle\" command to change these tables.");
+        }
+    }
+}
+
+/// Parse a signal name, e.g. "SIGSEGV", or a number from 1 to 15
+pub fn parse_signal(arg: &str) -> Option<Signal> {
+    match arg.parse::<i32>() {
+        Ok(n) if (1..=15).contains(&n) => Signal::from_c_int(n).ok(),
+        Ok(_) => None,
+        Err(_) => Signal::from_str(arg).ok(),
+    }
+}
+
+/// Signal description, as gdb reports it
+pub fn description(signal: Signal) -> &'static str {
+    match signal {
+        Signal::SIGHUP => "Hangup",
+        Signal::SIGINT => "Interrupt",
+        Signal::SIGQUIT => "Quit",
+        Signal::SIGILL => "Illegal instruction",
+        Signal::SIGTRAP => "Trace/breakpoint trap",
+        Signal::SIGABRT => "Aborted",
+        Signal::SIGBUS => "Bus error",
+        Signal::SIGFPE => "Arithmetic exception",
+        Signal::SIGKILL => "Killed",
+        Signal::SIGUSR1 => "User defined signal 1",
+        Signal::SIGSEGV => "Segmentation fault",
+        Signal::SIGUSR2 => "User defined signal 2",
+        Signal::SIGPIPE => "Broken pipe",
+        Signal::SIGALRM => "Alarm clock",
+        Signal::SIGTERM => "Terminated",
+        Signal::SIGSTKFLT => "Stack fault",
+        Signal::SIGCHLD => "Child status changed",
+        Signal::SIGCONT => "Continued",
+        Signal::SIGSTOP => "Stopped (signal)",
+        Signal::SIGTSTP => "Stopped (user)",
+        Signal::SIGTTIN => "Stopped (tty input)",
+        Signal::SIGTTOU => "Stopped (tty output)",
+        Signal::SIGURG => "Urgent I/O condition",
+        Signal::SIGXCPU => "CPU time limit exceeded",
+        Signal::SIGXFSZ => "File size limit exceeded",
+        Signal::SIGVTALRM => "Virtual timer expired",
+        Signal::SIGPROF => "Profiling timer expired",
+        Signal::SIGWINCH => "Window size changed",
+        Signal::SIGIO => "I/O possible",
+        Signal::SIGPWR => "Power fail/restart",
+        Signal::SIGSYS => "Bad system call",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle() {
+        let mut signals = Signals::default();
+        assert!(!signals.get(Signal::SIGALRM).stop);
+        assert!(!signals.get(Signal::SIGTRAP).pass);
+
+        let args = ["SIGUSR1", "nostop", "noprint", "11"];
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        let changed = signals.handle(&args).unwrap();
+        assert_eq!(changed, vec![Signal::SIGUSR1, Signal::SIGSEGV]);
+        assert_eq!(
+            signals.get(Signal::SIGSEGV),
+            Policy {
+                stop: false,
+                print: false,
+                pass: true,
+            }
+        );
+
+        let args = vec!["SIGSEGV".to_owned(), "stop".to_owned()];
+        signals.handle(&args).unwrap();
+        assert!(signals.get(Signal::SIGSEGV).print);
+        assert!(signals.handle(&["nopass".to_owned()]).is_err());
+        assert!(signals.handle(&["SIGBOGUS".to_owned()]).is_err());
+    }
+}
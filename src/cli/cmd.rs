@@ -3,10 +3,11 @@ use std::path::PathBuf;
 use failure::{bail, ensure};
 use structopt::{clap::AppSettings, StructOpt};
 
+use super::expr::{parse_expr, Expr};
 use super::Error;
 
 /// Interactive prompt commands
-#[derive(StructOpt, Debug)]
+#[derive(StructOpt, Debug, Clone)]
 #[structopt(raw(setting = "AppSettings::SubcommandRequired"))]
 #[structopt(raw(global_setting = "AppSettings::NoBinaryName"))]
 #[structopt(raw(global_setting = "AppSettings::VersionlessSubcommands"))]
@@ -26,6 +27,57 @@ pub enum Cmd {
         #[structopt(name = "ARGS")]
         args: Vec<String>,
     },
+    #[structopt(
+        name = "start",
+        about = "Start the debugged program stopping at the beginning of the \
+                 main procedure",
+        template = "{bin} {positionals}"
+    )]
+    #[structopt(raw(setting = "AppSettings::TrailingVarArg"))]
+    Start {
+        #[structopt(name = "ARGS")]
+        args: Vec<String>,
+    },
+    #[structopt(
+        name = "starti",
+        about = "Start the debugged program stopping at the first instruction",
+        template = "{bin} {positionals}"
+    )]
+    #[structopt(raw(setting = "AppSettings::TrailingVarArg"))]
+    Starti {
+        #[structopt(name = "ARGS")]
+        args: Vec<String>,
+    },
+    #[structopt(
+        name = "attach",
+        about = "Attach to a process outside of the debugger",
+        template = "{bin} {positionals}"
+    )]
+    Attach {
+        #[structopt(name = "PID")]
+        pid: usize,
+    },
+    #[structopt(
+        name = "detach",
+        about = "Detach the process, letting it run on outside the debugger",
+        template = "{bin}"
+    )]
+    Detach,
+    #[structopt(
+        name = "kill",
+        about = "Kill execution of program being debugged",
+        template = "{bin}"
+    )]
+    Kill,
+    #[structopt(
+        name = "thread",
+        about = "Use this command to switch between threads",
+        template = "{bin} {positionals}"
+    )]
+    Thread {
+        #[structopt(name = "NUM")]
+        num: Option<usize>,
+    },
     #[structopt(
         name = "continue",
         about = "Continue program being debugged, after signal or breakpoint",
@@ -77,8 +129,115 @@ pub enum Cmd {
         template = "{bin} {positionals}"
     )]
     Break {
-        #[structopt(name = "LOCATION", parse(try_from_str = "parse_addr"))]
-        loc: usize,
+        #[structopt(name = "LOCATION", parse(try_from_str = "parse_location"))]
+        loc: Location,
+        #[structopt(name = "CONDITION")]
+        cond: Option<String>,
+    },
+    #[structopt(
+        name = "tbreak",
+        about = "Set a temporary breakpoint",
+        template = "{bin} {positionals}"
+    )]
+    Tbreak {
+        #[structopt(name = "LOCATION", parse(try_from_str = "parse_location"))]
+        loc: Location,
+        #[structopt(name = "CONDITION")]
+        cond: Option<String>,
+    },
+    #[structopt(
+        name = "commands",
+        about = "Set commands to be executed when breakpoint N is hit",
+        template = "{bin} {positionals}"
+    )]
+    Commands {
+        #[structopt(name = "N")]
+        num: Option<usize>,
+    },
+    #[structopt(
+        name = "condition",
+        about = "Specify breakpoint number N to break only if COND is true",
+        template = "{bin} {positionals}"
+    )]
+    #[structopt(raw(setting = "AppSettings::AllowLeadingHyphen"))]
+    Condition {
+        #[structopt(name = "N")]
+        num: usize,
+        #[structopt(name = "COND")]
+        cond: Option<String>,
+    },
+    #[structopt(
+        name = "ignore",
+        about = "Set ignore-count of breakpoint number N to COUNT",
+        template = "{bin} {positionals}"
+    )]
+    Ignore {
+        #[structopt(name = "N")]
+        num: usize,
+        #[structopt(name = "COUNT")]
+        count: usize,
+    },
+    #[structopt(
+        name = "hbreak",
+        about = "Set a hardware assisted breakpoint",
+        template = "{bin} {positionals}"
+    )]
+    Hbreak {
+        #[structopt(name = "LOCATION", parse(try_from_str = "parse_location"))]
+        loc: Location,
+        #[structopt(name = "CONDITION")]
+        cond: Option<String>,
+    },
+    #[structopt(
+        name = "watch",
+        about = "Set a watchpoint for an expression",
+        template = "{bin} {positionals}"
+    )]
+    #[structopt(raw(setting = "AppSettings::AllowLeadingHyphen"))]
+    Watch {
+        #[structopt(name = "EXPRESSION")]
+        expr: String,
+    },
+    #[structopt(
+        name = "rwatch",
+        about = "Set a read watchpoint for an expression",
+        template = "{bin} {positionals}"
+    )]
+    #[structopt(raw(setting = "AppSettings::AllowLeadingHyphen"))]
+    Rwatch {
+        #[structopt(name = "EXPRESSION")]
+        expr: String,
+    },
+    #[structopt(
+        name = "awatch",
+        about = "Set a watchpoint for an expression",
+        template = "{bin} {positionals}"
+    )]
+    #[structopt(raw(setting = "AppSettings::AllowLeadingHyphen"))]
+    Awatch {
+        #[structopt(name = "EXPRESSION")]
+        expr: String,
+    },
+    #[structopt(
+        name = "handle",
+        about = "Specify how to handle signals",
+        template = "{bin} {positionals}"
+    )]
+    Handle {
+        #[structopt(name = "SIGNALS ACTIONS")]
+        args: Vec<String>,
+    },
+    #[structopt(
+        name = "disassemble",
+        about = "Disassemble a specified section of memory",
+        template = "{bin} {positionals}"
+    )]
+    Disassemble {
+        #[structopt(
+            name = "START[,END]|FUNC",
+            parse(try_from_str = "parse_span")
+        )]
+        span: Option<Span>,
     },
     #[structopt(
         name = "x",
@@ -89,8 +248,21 @@ pub enum Cmd {
     Examine {
         #[structopt(name = "FMT", parse(try_from_str = "parse_fmt"))]
         fmt: Option<Fmt>,
-        #[structopt(name = "ADDRESS", parse(try_from_str = "parse_addr"))]
-        addr: Option<usize>,
+        #[structopt(name = "ADDRESS", parse(try_from_str = "parse_expr"))]
+        addr: Option<Expr>,
+    },
+    #[structopt(
+        name = "print",
+        template = "print/FMT EXPRESSION",
+        about = "Print value of expression EXP"
+    )]
+    #[structopt(raw(alias = r#""p""#))]
+    #[structopt(raw(setting = "AppSettings::AllowLeadingHyphen"))]
+    Print {
+        #[structopt(name = "FMT", parse(try_from_str = "parse_fmt"))]
+        fmt: Option<Fmt>,
+        #[structopt(name = "EXPRESSION", parse(try_from_str = "parse_expr"))]
+        expr: Option<Expr>,
     },
     #[structopt(
         name = "file",
@@ -107,8 +279,10 @@ pub enum Cmd {
         about = "Commands that modify parts of the debug environment"
     )]
     #[structopt(raw(global_setting = "AppSettings::DisableHelpSubcommand"))]
+    #[structopt(raw(setting = "AppSettings::AllowLeadingHyphen"))]
     Set {
-        expr: Option<String>,
+        #[structopt(name = "EXPR")]
+        expr: Vec<String>,
         #[structopt(subcommand)]
         cmd: Option<Set>,
     },
@@ -124,8 +298,62 @@ pub enum Cmd {
     },
 }
 
+impl Cmd {
+    /// Command to run for an empty line after this one, gdb-style, or None
+    /// if this command doesn't repeat
+    pub fn repeat(&self) -> Option<Cmd> {
+        match self {
+            // Continue examining from the last address, in the last format
+            Cmd::Examine { .. } => Some(Cmd::Examine {
+                fmt: None,
+                addr: None,
+            }),
+            Cmd::Continue { .. }
+            | Cmd::Stepi { .. }
+            | Cmd::Disassemble { .. }
+            | Cmd::Print { .. }
+            | Cmd::Info { .. } => Some(self.clone()),
+            Cmd::Repeat
+            | Cmd::Run { .. }
+            | Cmd::Start { .. }
+            | Cmd::Starti { .. }
+            | Cmd::Attach { .. }
+            | Cmd::Detach
+            | Cmd::Kill
+            | Cmd::Thread { .. }
+            | Cmd::Delete { .. }
+            | Cmd::Disable { .. }
+            | Cmd::Enable { .. }
+            | Cmd::Break { .. }
+            | Cmd::Hbreak { .. }
+            | Cmd::Tbreak { .. }
+            | Cmd::Condition { .. }
+            | Cmd::Commands { .. }
+            | Cmd::Ignore { .. }
+            | Cmd::Watch { .. }
+            | Cmd::Rwatch { .. }
+            | Cmd::Awatch { .. }
+            | Cmd::Handle { .. }
+            | Cmd::File { .. }
+            | Cmd::Set { .. } => None,
+        }
+    }
+
+    /// Resumes the debugged program, ending a breakpoint's command list
+    pub fn resumes(&self) -> bool {
+        matches!(
+            self,
+            Cmd::Run { .. }
+                | Cmd::Start { .. }
+                | Cmd::Starti { .. }
+                | Cmd::Continue { .. }
+                | Cmd::Stepi { .. }
+        )
+    }
+}
+
 /// Show subcommands for showing /proc information
-#[derive(StructOpt, Debug)]
+#[derive(StructOpt, Debug, Clone)]
 pub enum Info {
     #[structopt(
         name = "proc",
@@ -145,6 +373,15 @@ pub enum Info {
         #[structopt(name = "NUM")]
         args: Vec<usize>,
     },
+    #[structopt(
+        name = "watchpoints",
+        template = "{bin} {positionals}",
+        about = "Status of specified watchpoints"
+    )]
+    Watchpoints {
+        #[structopt(name = "NUM")]
+        args: Vec<usize>,
+    },
     #[structopt(
         name = "registers",
         template = "{bin} {positionals}",
@@ -154,16 +391,40 @@ pub enum Info {
         #[structopt(name = "NAMES")]
         names: Vec<String>,
     },
+    #[structopt(
+        name = "all-registers",
+        template = "{bin} {positionals}",
+        about = "List of all registers and their contents"
+    )]
+    AllRegisters {
+        #[structopt(name = "NAMES")]
+        names: Vec<String>,
+    },
+    #[structopt(
+        name = "signals",
+        template = "{bin} {positionals}",
+        about = "What debugger does when program gets various signals"
+    )]
+    Signals {
+        #[structopt(name = "SIGNAL")]
+        signal: Option<String>,
+    },
+    #[structopt(
+        name = "threads",
+        template = "{bin}",
+        about = "Display currently known threads"
+    )]
+    Threads,
 }
 
-#[derive(StructOpt, Debug)]
+#[derive(StructOpt, Debug, Clone)]
 pub enum Proc {
     #[structopt(name = "mappings", about = "List of mapped memory regions")]
     Mappings,
 }
 
 /// Set subcommands for configuring debugger environment settings
-#[derive(StructOpt, Debug)]
+#[derive(StructOpt, Debug, Clone)]
 pub enum Set {
     #[structopt(
         name = "args",
@@ -174,10 +435,70 @@ pub enum Set {
         #[structopt(name = "ARGS")]
         args: Vec<String>,
     },
+    #[structopt(
+        name = "disable-randomization",
+        about = "Set disabling of debuggee's virtual address space randomization",
+        template = "{bin} {positionals}"
+    )]
+    DisableRandomization {
+        #[structopt(name = "on|off", parse(try_from_str = "parse_on_off"))]
+        value: Option<bool>,
+    },
+    #[structopt(
+        name = "follow-fork-mode",
+        about = "Set debugger response to a program call of fork or vfork",
+        template = "{bin} {positionals}"
+    )]
+    FollowForkMode {
+        #[structopt(
+            name = "parent|child",
+            parse(try_from_str = "parse_fork_mode")
+        )]
+        mode: ForkMode,
+    },
+    #[structopt(
+        name = "detach-on-fork",
+        about = "Set whether gdb will detach the child of a fork",
+        template = "{bin} {positionals}"
+    )]
+    DetachOnFork {
+        #[structopt(name = "on|off", parse(try_from_str = "parse_on_off"))]
+        value: Option<bool>,
+    },
+}
+
+/// Process to debug after a fork, set with `set follow-fork-mode`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ForkMode {
+    Parent,
+    Child,
+}
+
+/// Location spec for break, e.g. main, main+12, *0x401000 or hello.c:5
+#[derive(Debug, Clone, PartialEq)]
+pub enum Location {
+    /// Address expression, `*EXPR` (or a bare number)
+    Address(Expr),
+    /// Symbol plus an optional byte offset, `FUNC[+OFFSET]`
+    Symbol { name: String, offset: usize },
+    /// Source line, `FILE:LINE`
+    Line { file: String, line: usize },
+}
+
+/// Memory span for disassemble, e.g. main, 0x401126,0x401140 or
+/// 0x401126,+16
+#[derive(Debug, Clone, PartialEq)]
+pub enum Span {
+    /// Function containing a location
+    Function(Location),
+    /// From START up to, but not including, END
+    Range(Location, Location),
+    /// LENGTH bytes from START
+    Length(Location, usize),
 }
 
 /// Format for x, print, and display commands, i.e. x/FMT.
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Fmt {
     pub reverse: bool,
     pub repeat: Option<u64>,
@@ -238,7 +559,7 @@ fn parse_fmt(arg: &str) -> Result<Fmt, failure::Error> {
 }
 
 /// Parse an address string
-fn parse_addr(arg: &str) -> Result<usize, failure::Error> {
+pub(super) fn parse_addr(arg: &str) -> Result<usize, failure::Error> {
     ensure!(arg.len() > 0, "Cannot parse empty address string");
     let arg = arg.to_ascii_lowercase();
     if arg.starts_with("0x") {
@@ -256,18 +577,130 @@ fn parse_addr(arg: &str) -> Result<usize, failure::Error> {
     }
 }
 
+/// Parse a boolean setting, e.g. set disable-randomization off
+fn parse_on_off(arg: &str) -> Result<bool, failure::Error> {
+    match arg {
+        "on" | "1" | "yes" | "enable" => Ok(true),
+        "off" | "0" | "no" | "disable" => Ok(false),
+        _ => bail!("\"on\" or \"off\" expected."),
+    }
+}
+
+/// Parse a fork mode, e.g. set follow-fork-mode child
+fn parse_fork_mode(arg: &str) -> Result<ForkMode, failure::Error> {
+    match arg {
+        "parent" => Ok(ForkMode::Parent),
+        "child" => Ok(ForkMode::Child),
+        _ => bail!("Undefined item: \"{}\".", arg),
+    }
+}
+
+/// Parse a location string
+fn parse_location(arg: &str) -> Result<Location, failure::Error> {
+    ensure!(!arg.is_empty(), "Cannot parse empty location string");
+    if let Some(addr) = arg.strip_prefix('*') {
+        return Ok(Location::Address(parse_expr(addr)?));
+    }
+    if arg.starts_with(char::is_numeric) {
+        return Ok(Location::Address(Expr::Int(parse_addr(arg)? as i64)));
+    }
+    // FILE:LINE, but not a qualified name like ns::func
+    if let Some(idx) = arg.rfind(':') {
+        if let Ok(line) = arg[idx + 1..].parse() {
+            let file = arg[..idx].to_owned();
+            return Ok(Location::Line { file, line });
+        }
+    }
+    let (name, offset) = match arg.find('+') {
+        Some(idx) => (&arg[..idx], parse_addr(&arg[idx + 1..])?),
+        None => (arg, 0),
+    };
+    Ok(Location::Symbol {
+        name: name.to_owned(),
+        offset,
+    })
+}
+
+/// Parse a span string
+fn parse_span(arg: &str) -> Result<Span, failure::Error> {
+    let (start, end) = match arg.find(',') {
+        Some(idx) => (arg[..idx].trim(), arg[idx + 1..].trim()),
+        None => return Ok(Span::Function(parse_location(arg.trim())?)),
+    };
+    let start = parse_location(start)?;
+    match end.strip_prefix('+') {
+        Some(len) => Ok(Span::Length(start, parse_addr(len)?)),
+        None => Ok(Span::Range(start, parse_location(end)?)),
+    }
+}
+
+/// Commands whose last argument is an expression that may contain spaces
+const EXPRESSION_COMMANDS: &[&str] = &[
+    "x",
+    "print",
+    "p",
+    "break",
+    "b",
+    "br",
+    "hbreak",
+    "tbreak",
+    "watch",
+    "rwatch",
+    "awatch",
+    "condition",
+    "disassemble",
+];
+
+/// Commands taking an optional /FMT before their expression
+const FORMAT_COMMANDS: &[&str] = &["x", "print", "p"];
+
+/// Commands taking a location and an optional `if CONDITION`
+const BREAK_COMMANDS: &[&str] = &["break", "b", "br", "hbreak", "tbreak"];
+
+/// Commands taking a breakpoint number before their expression
+const NUMBERED_COMMANDS: &[&str] = &["condition"];
+
 /// Tokenize and parse a command line string
 pub fn parse_command(line: &str) -> Result<Cmd, Error> {
-    let cmd = match line.len() {
-        0 => Ok(Cmd::Repeat),
-        1 => Cmd::from_iter_safe(vec![line]),
-        _ => {
-            let line = match &line[..2] {
-                "x/" | "p/" => line.replacen("/", " ", 1),
-                _ => line.to_owned(),
-            };
-            Cmd::from_iter_safe(line.split_whitespace())
+    let name_len = line
+        .find(|c: char| c.is_whitespace() || c == '/')
+        .unwrap_or(line.len());
+    let (name, rest) = line.split_at(name_len);
+
+    let cmd = if line.trim().is_empty() {
+        Ok(Cmd::Repeat)
+    } else if EXPRESSION_COMMANDS.contains(&name) {
+        // Keep the expression in one argument, after any FMT
+        let mut args = vec![name];
+        let mut rest = rest.trim();
+        if FORMAT_COMMANDS.contains(&name) {
+            match rest.strip_prefix('/') {
+                Some(fmt) => {
+                    let len =
+                        fmt.find(char::is_whitespace).unwrap_or(fmt.len());
+                    args.push(&fmt[..len]);
+                    rest = fmt[len..].trim();
+                }
+                None => args.push(""),
+            }
+        }
+        if NUMBERED_COMMANDS.contains(&name) {
+            let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            args.push(&rest[..len]);
+            rest = rest[len..].trim();
+        }
+        if BREAK_COMMANDS.contains(&name) {
+            if let Some(idx) = rest.find(" if ") {
+                args.push(rest[..idx].trim());
+                rest = rest[idx + 4..].trim();
+            }
+        }
+        if !rest.is_empty() {
+            args.push(rest);
         }
+        Cmd::from_iter_safe(args)
+    } else {
+        Cmd::from_iter_safe(line.split_whitespace())
     }
     .map_err(|e| Error::command(line, e))?;
 
@@ -313,4 +746,191 @@ mod tests {
         assert_eq!(parse_addr("0o1111").ok(), Some(0o1111));
         assert_eq!(parse_addr("1234").ok(), Some(1234));
     }
+
+    #[test]
+    fn test_parse_location() {
+        assert_eq!(
+            parse_location("*0x401000").ok(),
+            Some(Location::Address(Expr::Int(0x401000)))
+        );
+        assert_eq!(
+            parse_location("main").ok(),
+            Some(Location::Symbol {
+                name: "main".to_owned(),
+                offset: 0,
+            })
+        );
+        assert_eq!(
+            parse_location("main+12").ok(),
+            Some(Location::Symbol {
+                name: "main".to_owned(),
+                offset: 12,
+            })
+        );
+        assert_eq!(
+            parse_location("hello.c:5").ok(),
+            Some(Location::Line {
+                file: "hello.c".to_owned(),
+                line: 5,
+            })
+        );
+        assert_eq!(
+            parse_location("ns::func").ok(),
+            Some(Location::Symbol {
+                name: "ns::func".to_owned(),
+                offset: 0,
+            })
+        );
+        assert_eq!(
+            parse_location("*main").ok(),
+            Some(Location::Address(Expr::Symbol("main".to_owned())))
+        );
+        assert!(parse_location("*main+").is_err());
+    }
+
+    #[test]
+    fn test_repeat() {
+        let cmd = parse_command("x/4gx $sp").unwrap();
+        match cmd.repeat() {
+            Some(Cmd::Examine {
+                fmt: None,
+                addr: None,
+            }) => (),
+            cmd => panic!("unexpected repeat {:?}", cmd),
+        }
+        let cmd = parse_command("stepi 2").unwrap();
+        assert!(matches!(cmd.repeat(), Some(Cmd::Stepi { n: 2 })));
+        assert!(parse_command("run").unwrap().repeat().is_none());
+        assert!(parse_command("delete 1").unwrap().repeat().is_none());
+        assert!(matches!(parse_command("  ").unwrap(), Cmd::Repeat));
+    }
+
+    #[test]
+    fn test_commands() {
+        let cmd = parse_command("commands 2").unwrap();
+        assert!(matches!(cmd, Cmd::Commands { num: Some(2) }));
+        let cmd = parse_command("commands").unwrap();
+        assert!(matches!(cmd, Cmd::Commands { num: None }));
+        assert!(parse_command("commands x").is_err());
+        assert!(parse_command("continue").unwrap().resumes());
+        assert!(parse_command("stepi").unwrap().resumes());
+        assert!(parse_command("start").unwrap().resumes());
+        assert!(!parse_command("x/4gx $sp").unwrap().resumes());
+        assert!(!parse_command("commands 1").unwrap().resumes());
+    }
+
+    #[test]
+    fn test_attach() {
+        let cmd = parse_command("attach 1234").unwrap();
+        assert!(matches!(cmd, Cmd::Attach { pid: 1234 }));
+        assert!(cmd.repeat().is_none());
+        assert!(parse_command("attach").is_err());
+        assert!(parse_command("attach x").is_err());
+    }
+
+    #[test]
+    fn test_kill() {
+        let cmd = parse_command("kill").unwrap();
+        assert!(matches!(cmd, Cmd::Kill));
+        assert!(!cmd.resumes());
+        assert!(cmd.repeat().is_none());
+        assert!(parse_command("kill 1").is_err());
+    }
+
+    #[test]
+    fn test_thread() {
+        let cmd = parse_command("thread 2").unwrap();
+        assert!(matches!(cmd, Cmd::Thread { num: Some(2) }));
+        assert!(!cmd.resumes());
+        let cmd = parse_command("thread").unwrap();
+        assert!(matches!(cmd, Cmd::Thread { num: None }));
+        assert!(parse_command("thread x").is_err());
+        let cmd = parse_command("info threads").unwrap();
+        assert!(matches!(cmd, Cmd::Info { cmd: Info::Threads }));
+    }
+
+    #[test]
+    fn test_fork_settings() {
+        assert_eq!(parse_fork_mode("parent").ok(), Some(ForkMode::Parent));
+        assert_eq!(parse_fork_mode("child").ok(), Some(ForkMode::Child));
+        assert!(parse_fork_mode("Child").is_err());
+        assert!(parse_fork_mode("").is_err());
+        assert!(matches!(
+            parse_command("set follow-fork-mode child").unwrap(),
+            Cmd::Set {
+                cmd: Some(Set::FollowForkMode {
+                    mode: ForkMode::Child
+                }),
+                ..
+            }
+        ));
+        assert!(parse_command("set follow-fork-mode").is_err());
+        assert!(parse_command("set follow-fork-mode both").is_err());
+        assert!(matches!(
+            parse_command("set detach-on-fork off").unwrap(),
+            Cmd::Set {
+                cmd: Some(Set::DetachOnFork { value: Some(false) }),
+                ..
+            }
+        ));
+        assert!(matches!(
+            parse_command("set detach-on-fork").unwrap(),
+            Cmd::Set {
+                cmd: Some(Set::DetachOnFork { value: None }),
+                ..
+            }
+        ));
+        assert!(parse_command("set detach-on-fork maybe").is_err());
+    }
+
+    #[test]
+    fn test_parse_condition() {
+        match parse_command("break hello.c:5 if $rax == 1").unwrap() {
+            Cmd::Break {
+                loc: Location::Line { line: 5, .. },
+                cond: Some(cond),
+            } => assert_eq!(cond, "$rax == 1"),
+            cmd => panic!("unexpected command {:?}", cmd),
+        }
+        match parse_command("b main").unwrap() {
+            Cmd::Break { cond: None, .. } => (),
+            cmd => panic!("unexpected command {:?}", cmd),
+        }
+        match parse_command("tbreak main if $rdi").unwrap() {
+            Cmd::Tbreak {
+                loc: Location::Symbol { .. },
+                cond: Some(cond),
+            } => assert_eq!(cond, "$rdi"),
+            cmd => panic!("unexpected command {:?}", cmd),
+        }
+        match parse_command("condition 2 -$rdi < 0").unwrap() {
+            Cmd::Condition {
+                num: 2,
+                cond: Some(cond),
+            } => assert_eq!(cond, "-$rdi < 0"),
+            cmd => panic!("unexpected command {:?}", cmd),
+        }
+        assert!(matches!(
+            parse_command("condition 2").unwrap(),
+            Cmd::Condition { num: 2, cond: None }
+        ));
+    }
+
+    #[test]
+    fn test_parse_span() {
+        let main = Location::Symbol {
+            name: "main".to_owned(),
+            offset: 0,
+        };
+        assert_eq!(parse_span("main").ok(), Some(Span::Function(main.clone())));
+        assert_eq!(
+            parse_span("main,+16").ok(),
+            Some(Span::Length(main.clone(), 16))
+        );
+        assert_eq!(
+            parse_span("main,0x401140").ok(),
+            Some(Span::Range(main, Location::Address(Expr::Int(0x401140))))
+        );
+        assert!(parse_span("main,+").is_err());
+    }
 }
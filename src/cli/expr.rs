@@ -0,0 +1,496 @@
+use std::fmt;
+
+use failure::{bail, ensure};
+
+use super::cmd::parse_addr;
+
+/// C-like expression, as accepted by print, x, set and break *EXPR
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// Integer or character literal
+    Int(i64),
+    /// Register, convenience variable or value history, `$NAME`, `$N`, `$`
+    /// or `$$`, without the leading `$`
+    Dollar(String),
+    /// Symbol from the object file
+    Symbol(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// Cast, `(TYPE) EXPR`
+    Cast(Type, Box<Expr>),
+    /// Object in memory, `{TYPE} ADDR`
+    Memory(Type, Box<Expr>),
+    /// Assignment, `LVALUE = EXPR`
+    Assign(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+    Deref,
+    AddrOf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+/// Binary operators by precedence, loosest first
+const PRECEDENCE: &[&[(&str, BinaryOp)]] = &[
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[("|", BinaryOp::BitOr)],
+    &[("^", BinaryOp::BitXor)],
+    &[("&", BinaryOp::BitAnd)],
+    &[("==", BinaryOp::Eq), ("!=", BinaryOp::Ne)],
+    &[
+        ("<", BinaryOp::Lt),
+        ("<=", BinaryOp::Le),
+        (">", BinaryOp::Gt),
+        (">=", BinaryOp::Ge),
+    ],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[
+        ("*", BinaryOp::Mul),
+        ("/", BinaryOp::Div),
+        ("%", BinaryOp::Rem),
+    ],
+];
+
+/// Operator tokens, longest first
+const OPERATORS: &[&str] = &[
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%",
+    "<", ">", "&", "|", "^", "!", "~", "=", "(", ")", "{", "}",
+];
+
+/// Integer, pointer and the few other C types expressions can name
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    /// Integer of a size in bytes
+    Int { size: usize, signed: bool },
+    /// Pointer to another type
+    Pointer(Box<Type>),
+    /// Function, the target of code pointers
+    Function,
+    /// No value, e.g. an unset convenience variable
+    Void,
+}
+
+impl Type {
+    pub const INT: Type = Type::Int {
+        size: 4,
+        signed: true,
+    };
+    pub const LONG: Type = Type::Int {
+        size: 8,
+        signed: true,
+    };
+
+    /// Size in bytes, with void and functions sized like GNU C does
+    pub fn size(&self) -> usize {
+        match self {
+            Type::Int { size, .. } => *size,
+            Type::Pointer(_) => 8,
+            Type::Function | Type::Void => 1,
+        }
+    }
+
+    /// Parse a C type name, e.g. "unsigned int" or "char **"
+    pub fn parse(name: &str) -> Result<Type, failure::Error> {
+        let base = name.trim_end_matches(&['*', ' '][..]);
+        let words: Vec<_> = base.split_whitespace().collect();
+        let (signed, words) = match words.first() {
+            Some(&"unsigned") => (false, &words[1..]),
+            Some(&"signed") => (true, &words[1..]),
+            _ => (true, &words[..]),
+        };
+        let mut ty = match (words.join(" ").as_str(), signed) {
+            ("", false) | ("int", _) => Type::Int { size: 4, signed },
+            ("char", _) => Type::Int { size: 1, signed },
+            ("short", _) | ("short int", _) => Type::Int { size: 2, signed },
+            ("long", _) | ("long int", _) | ("long long", _) => {
+                Type::Int { size: 8, signed }
+            }
+            ("int8_t", true) => Type::Int { size: 1, signed },
+            ("uint8_t", true) => Type::Int {
+                size: 1,
+                signed: false,
+            },
+            ("int16_t", true) => Type::Int { size: 2, signed },
+            ("uint16_t", true) => Type::Int {
+                size: 2,
+                signed: false,
+            },
+            ("int32_t", true) => Type::Int { size: 4, signed },
+            ("uint32_t", true) => Type::Int {
+                size: 4,
+                signed: false,
+            },
+            ("int64_t", true) => Type::Int { size: 8, signed },
+            ("uint64_t", true) | ("size_t", true) => Type::Int {
+                size: 8,
+                signed: false,
+            },
+            ("void", true) => Type::Void,
+            _ => bail!("No symbol \"{}\" in current context.", base.trim()),
+        };
+        for _ in 0..name.matches('*').count() {
+            ty = Type::Pointer(Box::new(ty));
+        }
+        ensure!(
+            ty != Type::Void,
+            "Attempt to use a type name as an expression"
+        );
+        Ok(ty)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int { size, signed } => {
+                let name = match size {
+                    1 => "char",
+                    2 => "short",
+                    4 => "int",
+                    _ => "long",
+                };
+                match signed {
+                    true => write!(f, "{}", name),
+                    false => write!(f, "unsigned {}", name),
+                }
+            }
+            Type::Pointer(ty) => match **ty {
+                Type::Function => write!(f, "void (*)()"),
+                Type::Pointer(_) => write!(f, "{}*", ty),
+                _ => write!(f, "{} *", ty),
+            },
+            Type::Function => write!(f, "void ()"),
+            Type::Void => write!(f, "void"),
+        }
+    }
+}
+
+/// Words that start a type name in a cast
+const TYPE_WORDS: &[&str] = &[
+    "char", "short", "int", "long", "signed", "unsigned", "void", "int8_t",
+    "uint8_t", "int16_t", "uint16_t", "int32_t", "uint32_t", "int64_t",
+    "uint64_t", "size_t",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Dollar(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+/// Split an expression into tokens and their offsets
+fn tokenize(arg: &str) -> Result<Vec<(Token, usize)>, failure::Error> {
+    let mut tokens = Vec::new();
+    let mut rest = arg;
+    while let Some(c) = rest.chars().next() {
+        let offset = arg.len() - rest.len();
+        let word_len = |s: &str| {
+            s.find(|c: char| !(c.is_alphanumeric() || "_.@$".contains(c)))
+                .unwrap_or(s.len())
+        };
+        let (token, len) = if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+            continue;
+        } else if c.is_ascii_digit() {
+            let len = word_len(rest);
+            match parse_addr(&rest[..len]) {
+                Ok(n) => (Token::Int(n as i64), len),
+                Err(_) => bail!("Invalid number \"{}\".", &rest[..len]),
+            }
+        } else if c == '\'' {
+            let chars: Vec<char> = rest.chars().take(4).collect();
+            match chars[..] {
+                ['\'', '\\', e, '\'', ..] => {
+                    let c = match e {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        '0' => '\0',
+                        c => c,
+                    };
+                    (Token::Int(c as i64), 3 + e.len_utf8())
+                }
+                ['\'', c, '\'', ..] => (Token::Int(c as i64), 2 + c.len_utf8()),
+                _ => bail!("Unmatched single quote."),
+            }
+        } else if c == '$' {
+            let len = 1 + word_len(&rest[1..]);
+            (Token::Dollar(rest[1..len].to_owned()), len)
+        } else if c.is_alphabetic() || c == '_' {
+            let len = word_len(rest);
+            (Token::Ident(rest[..len].to_owned()), len)
+        } else {
+            match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                Some(op) => (Token::Op(op), op.len()),
+                None => bail!("Invalid character '{}' in expression.", c),
+            }
+        };
+        tokens.push((token, offset));
+        rest = &rest[len..];
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser over expression tokens
+struct Parser<'a> {
+    arg: &'a str,
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Consume an operator if it's next
+    fn eat(&mut self, op: &str) -> bool {
+        match self.peek() {
+            Some(Token::Op(next)) if *next == op => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), failure::Error> {
+        match self.eat(op) {
+            true => Ok(()),
+            false => Err(self.syntax_error()),
+        }
+    }
+
+    /// gdb's syntax error, quoting the rest of the expression
+    fn syntax_error(&self) -> failure::Error {
+        let rest = match self.tokens.get(self.pos) {
+            Some((_, offset)) => &self.arg[*offset..],
+            None => "",
+        };
+        failure::err_msg(format!(
+            "A syntax error in expression, near `{}'.",
+            rest
+        ))
+    }
+
+    fn assignment(&mut self) -> Result<Expr, failure::Error> {
+        let lhs = self.binary(0)?;
+        match self.eat("=") {
+            true => {
+                Ok(Expr::Assign(Box::new(lhs), Box::new(self.assignment()?)))
+            }
+            false => Ok(lhs),
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, failure::Error> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(next)) => PRECEDENCE[level]
+                    .iter()
+                    .find(|(op, _)| op == next)
+                    .map(|&(_, op)| op),
+                _ => None,
+            };
+            match op {
+                Some(op) => {
+                    self.pos += 1;
+                    let rhs = self.binary(level + 1)?;
+                    lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+                }
+                None => break Ok(lhs),
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, failure::Error> {
+        let op = match self.peek() {
+            Some(Token::Op("-")) => Some(UnaryOp::Neg),
+            Some(Token::Op("!")) => Some(UnaryOp::Not),
+            Some(Token::Op("~")) => Some(UnaryOp::BitNot),
+            Some(Token::Op("*")) => Some(UnaryOp::Deref),
+            Some(Token::Op("&")) => Some(UnaryOp::AddrOf),
+            _ => None,
+        };
+        if let Some(op) = op {
+            self.pos += 1;
+            return Ok(Expr::Unary(op, Box::new(self.unary()?)));
+        }
+        if self.eat("{") {
+            let ty = self.type_name("}")?;
+            return Ok(Expr::Memory(ty, Box::new(self.unary()?)));
+        }
+        let cast = match self.tokens.get(self.pos + 1) {
+            Some((Token::Ident(word), _)) => {
+                TYPE_WORDS.contains(&word.as_str())
+            }
+            _ => false,
+        };
+        if cast && self.eat("(") {
+            let ty = self.type_name(")")?;
+            return Ok(Expr::Cast(ty, Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    /// Parse a type name up to a closing delimiter
+    fn type_name(&mut self, close: &str) -> Result<Type, failure::Error> {
+        let mut name = String::new();
+        loop {
+            match self.next() {
+                Some(Token::Ident(word)) => {
+                    name.push(' ');
+                    name.push_str(&word);
+                }
+                Some(Token::Op("*")) => name.push('*'),
+                Some(Token::Op(op)) if op == close => break,
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.syntax_error());
+                }
+            }
+        }
+        Type::parse(&name)
+    }
+
+    fn primary(&mut self) -> Result<Expr, failure::Error> {
+        match self.next() {
+            Some(Token::Int(n)) => Ok(Expr::Int(n)),
+            Some(Token::Dollar(name)) => Ok(Expr::Dollar(name)),
+            Some(Token::Ident(name)) => Ok(Expr::Symbol(name)),
+            Some(Token::Op("(")) => {
+                let expr = self.assignment()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            _ => {
+                self.pos -= 1;
+                Err(self.syntax_error())
+            }
+        }
+    }
+}
+
+/// Parse an expression, e.g. `*(long *)($rsp + 8)` or `$rax = 0x10`
+pub fn parse_expr(arg: &str) -> Result<Expr, failure::Error> {
+    let mut parser = Parser {
+        arg,
+        tokens: tokenize(arg)?,
+        pos: 0,
+    };
+    let expr = parser.assignment()?;
+    match parser.pos < parser.tokens.len() {
+        true => Err(parser.syntax_error()),
+        false => Ok(expr),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(n: i64) -> Box<Expr> {
+        Box::new(Expr::Int(n))
+    }
+
+    #[test]
+    fn test_parse_expr() {
+        assert_eq!(
+            parse_expr("1 + 2 * 3").ok(),
+            Some(Expr::Binary(
+                BinaryOp::Add,
+                int(1),
+                Box::new(Expr::Binary(BinaryOp::Mul, int(2), int(3)))
+            ))
+        );
+        assert_eq!(
+            parse_expr("*(long *)($rsp+8)").ok(),
+            Some(Expr::Unary(
+                UnaryOp::Deref,
+                Box::new(Expr::Cast(
+                    Type::Pointer(Box::new(Type::LONG)),
+                    Box::new(Expr::Binary(
+                        BinaryOp::Add,
+                        Box::new(Expr::Dollar("rsp".to_owned())),
+                        int(8)
+                    ))
+                ))
+            ))
+        );
+        assert_eq!(
+            parse_expr("{int}0x601040 = -5").ok(),
+            Some(Expr::Assign(
+                Box::new(Expr::Memory(Type::INT, int(0x601040))),
+                Box::new(Expr::Unary(UnaryOp::Neg, int(5)))
+            ))
+        );
+        assert_eq!(
+            parse_expr("main+4 == 'A'").ok(),
+            Some(Expr::Binary(
+                BinaryOp::Eq,
+                Box::new(Expr::Binary(
+                    BinaryOp::Add,
+                    Box::new(Expr::Symbol("main".to_owned())),
+                    int(4)
+                )),
+                int(65)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_expr_error() {
+        assert!(parse_expr("1 +").is_err());
+        assert!(parse_expr("(1").is_err());
+        assert!(parse_expr("{float}0x1000").is_err());
+        assert!(parse_expr("1 2").is_err());
+    }
+
+    #[test]
+    fn test_parse_expr_unicode() {
+        let sum = Expr::Binary(BinaryOp::Add, int(1), int(2));
+        assert_eq!(parse_expr("1\u{a0}+\u{3000}2").ok(), Some(sum));
+        assert_eq!(parse_expr("'\\\u{e9}'").ok(), Some(Expr::Int(0xe9)));
+        assert_eq!(parse_expr("'\u{e9}'").ok(), Some(Expr::Int(0xe9)));
+        assert!(parse_expr("1 \u{e9}").is_err());
+    }
+}
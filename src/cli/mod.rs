@@ -8,7 +8,12 @@ mod opt;
 pub use opt::Opt;
 
 mod cmd;
-pub use cmd::{parse_command, Cmd, Fmt, Info, Proc, Set};
+pub use cmd::{
+    parse_command, Cmd, Fmt, ForkMode, Info, Location, Proc, Set, Span,
+};
+
+mod expr;
+pub use expr::{parse_expr, BinaryOp, Expr, Type, UnaryOp};
 
 pub fn prompt_yes_no<P: AsRef<str>>(prompt: P) -> bool {
     let mut rl = Editor::<()>::new();
@@ -41,3 +46,29 @@ pub fn prompt_yes_no<P: AsRef<str>>(prompt: P) -> bool {
         }
     }
 }
+
+/// Read command lines, gdb-style, up to a line saying just "end"
+pub fn read_command_list() -> Vec<String> {
+    let mut rl = Editor::<()>::new();
+    let mut lines = Vec::new();
+    loop {
+        match rl.readline(">") {
+            Ok(ref line) if line.trim() == "end" => break,
+            Ok(line) => {
+                if !line.trim().is_empty() {
+                    lines.push(line.trim().to_owned());
+                }
+            }
+            Err(ReadlineError::Interrupted) => {
+                println!("Quit");
+                break;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                println!("error: {:?}", err);
+                break;
+            }
+        }
+    }
+    lines
+}
@@ -9,4 +9,24 @@ pub struct Opt {
     pub prog: Option<PathBuf>,
     #[structopt(name = "ARGS")]
     pub args: Vec<String>,
+    /// Attach to a running process
+    #[structopt(short = "p", long = "pid", name = "PID")]
+    pub pid: Option<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pid() {
+        let opt = Opt::from_iter_safe(vec!["shortstop", "-p", "1234"]).unwrap();
+        assert_eq!(opt.pid, Some(1234));
+        assert!(opt.prog.is_none());
+        let opt = Opt::from_iter_safe(vec!["shortstop", "--pid=1234"]).unwrap();
+        assert_eq!(opt.pid, Some(1234));
+        let opt = Opt::from_iter_safe(vec!["shortstop", "a.out"]).unwrap();
+        assert_eq!(opt.pid, None);
+        assert!(Opt::from_iter_safe(vec!["shortstop", "-p", "x"]).is_err());
+    }
 }
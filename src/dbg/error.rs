@@ -46,6 +46,22 @@ pub enum ErrorKind {
     // NotSupported,
     /// Process event related error
     ProcessEvent,
+    /// Wait status the debugger doesn't handle, as printed
+    WaitStatus(String),
+    /// Malformed /proc file contents
+    ProcFormat(String),
+    /// Register read or write error
+    Registers,
+    /// Load address of a position independent executable not found
+    LoadBias,
+    /// All debug registers in use
+    HardwareLimit,
+    /// Attaching to a process failed, with the reason
+    Attach(String),
+    /// No such thread of the process, by thread ID
+    Thread(usize),
+    /// Resuming a vfork parent would wait on the child held stopped
+    VforkHeld,
 }
 
 impl ErrorKind {
@@ -73,9 +89,38 @@ impl fmt::Display for ErrorKind {
             }
             ErrorKind::ProcessEvent => {
                 write!(f, "Error handling process event")
-            } // ErrorKind::NotSupported => {
-              //     write!(f, "Not supported on this target")
-              // }
+            }
+            ErrorKind::WaitStatus(ref status) => {
+                write!(f, "Unexpected process status: {}", status)
+            }
+            ErrorKind::ProcFormat(ref line) => {
+                write!(f, "Unexpected /proc format: {}", line)
+            }
+            ErrorKind::Registers => {
+                write!(f, "Couldn't access registers")
+            }
+            ErrorKind::LoadBias => {
+                write!(f, "Couldn't find where the program was loaded")
+            }
+            ErrorKind::HardwareLimit => write!(
+                f,
+                "Could not insert hardware breakpoints:\n\
+                 You may have requested too many hardware \
+                 breakpoints/watchpoints."
+            ),
+            ErrorKind::Attach(ref reason) => {
+                write!(f, "ptrace: {}.", reason)
+            }
+            ErrorKind::Thread(tid) => write!(f, "Unknown thread {}.", tid),
+            ErrorKind::VforkHeld => write!(
+                f,
+                "Can not resume the parent process over vfork in the \
+                 foreground while holding the child stopped.  Try \
+                 \"set detach-on-fork\"."
+            ),
+            // ErrorKind::NotSupported => {
+            //     write!(f, "Not supported on this target")
+            // }
         }
     }
 }
@@ -0,0 +1,129 @@
+use super::Address;
+
+/// Number of x86 debug address registers, DR0-DR3
+pub const SLOTS: usize = 4;
+
+/// DR6 bits set for the debug address registers that triggered
+const DR6_HITS: u64 = 0xf;
+
+/// Breakpoint kinds, by how they are inserted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// INT3 written over the instruction
+    Software,
+    /// Instruction breakpoint in a debug register
+    Hardware,
+    /// Data breakpoint on writes
+    Watch,
+    /// Data breakpoint on reads. x86 can't trap reads alone, so this traps
+    /// reads and writes.
+    ReadWatch,
+    /// Data breakpoint on reads and writes
+    AccessWatch,
+    /// Watchpoint checked after every single step, for values no debug
+    /// register can watch
+    SoftwareWatch,
+}
+
+impl Kind {
+    /// Inserted in a debug register
+    pub fn is_hardware(self) -> bool {
+        match self {
+            Kind::Software | Kind::SoftwareWatch => false,
+            Kind::Hardware | Kind::Watch | Kind::ReadWatch => true,
+            Kind::AccessWatch => true,
+        }
+    }
+
+    /// Watches data rather than instructions
+    pub fn is_watchpoint(self) -> bool {
+        match self {
+            Kind::Watch | Kind::ReadWatch | Kind::AccessWatch => true,
+            Kind::SoftwareWatch => true,
+            Kind::Software | Kind::Hardware => false,
+        }
+    }
+
+    /// DR7 R/W field, the access that triggers the breakpoint
+    fn access(self) -> u64 {
+        match self {
+            Kind::Software | Kind::Hardware | Kind::SoftwareWatch => 0b00,
+            Kind::Watch => 0b01,
+            Kind::ReadWatch | Kind::AccessWatch => 0b11,
+        }
+    }
+}
+
+/// Debug register assignment, an address range and the access to trap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub addr: Address,
+    pub len: usize,
+    pub kind: Kind,
+}
+
+impl Slot {
+    /// Check a range can be watched by one debug register, i.e. 1, 2, 4 or 8
+    /// bytes aligned to its length
+    pub fn fits(addr: Address, len: usize) -> bool {
+        [1, 2, 4, 8].contains(&len) && addr & (len - 1) == 0
+    }
+
+    /// DR7 LEN field
+    fn len_bits(&self) -> u64 {
+        match (self.kind, self.len) {
+            (Kind::Hardware, _) | (_, 1) => 0b00,
+            (_, 2) => 0b01,
+            (_, 8) => 0b10,
+            _ => 0b11,
+        }
+    }
+}
+
+/// Encode DR7 for the slots in use, locally enabling each one
+pub fn dr7(slots: &[Option<Slot>]) -> u64 {
+    let mut dr7 = 0;
+    for (i, slot) in slots.iter().enumerate() {
+        if let Some(slot) = slot {
+            dr7 |= 1 << (2 * i);
+            dr7 |= slot.kind.access() << (16 + 4 * i);
+            dr7 |= slot.len_bits() << (18 + 4 * i);
+        }
+    }
+    dr7
+}
+
+/// Slots that triggered, according to DR6
+pub fn hits(dr6: u64) -> impl Iterator<Item = usize> {
+    (0..SLOTS).filter(move |i| dr6 & DR6_HITS & (1 << i) != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dr7() {
+        let exec = Slot {
+            addr: 0x401126,
+            len: 1,
+            kind: Kind::Hardware,
+        };
+        let write = Slot {
+            addr: 0x404028,
+            len: 4,
+            kind: Kind::Watch,
+        };
+        let access = Slot {
+            addr: 0x404030,
+            len: 8,
+            kind: Kind::AccessWatch,
+        };
+        assert_eq!(dr7(&[Some(exec), None, None, None]), 0x1);
+        assert_eq!(dr7(&[None, Some(write), None, None]), 0xd0_0004);
+        assert_eq!(dr7(&[None, None, None, Some(access)]), 0xb000_0040);
+        assert!(Slot::fits(0x404028, 8) && !Slot::fits(0x404024, 8));
+        assert!(!Slot::fits(0x404028, 3));
+        assert_eq!(hits(0xffff_4ff2).collect::<Vec<_>>(), vec![1]);
+    }
+}
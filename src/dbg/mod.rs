@@ -1,41 +1,108 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
-use std::path::{Path, PathBuf};
-
-use failure::ResultExt;
 
 mod error;
 pub use error::{Error, ErrorKind, Result};
 
+pub mod hw;
+pub use hw::Kind;
+use hw::Slot;
+
+pub use nix::sys::signal::Signal;
+
 mod proc;
-use proc::{Proc, ProcReader};
+use proc::{Proc, ProcReader, AT_ENTRY};
 
 mod ptrace;
 use ptrace::Ptraced;
 
+pub mod regs;
+pub use regs::{FpRegisters, Registers};
+
+use crate::obj::Binary;
+
 pub type Address = usize;
 pub type Pid = usize;
 
 /// Debugger with generic debugged progam type
 #[derive(Debug)]
 pub struct Debugger {
-    prog: PathBuf,
+    bin: Binary,
     target: Option<Box<dyn Target>>,
+    /// Inserted INT3s by address, with the saved instruction byte and the
+    /// number of breakpoints sharing it
+    int3s: HashMap<Address, (u8, usize)>,
+    /// Debug register slots in use, with the number of breakpoints sharing
+    /// each
+    slots: [Option<(Slot, usize)>; hw::SLOTS],
 }
 
 /// Generic debugged program interface
 pub trait Debugged: Debug {
     /// Program counter
     fn pc(&mut self) -> Result<usize>;
+    /// General purpose registers
+    fn registers(&mut self) -> Result<Registers>;
+    /// Write general purpose registers
+    fn set_registers(&mut self, regs: &Registers) -> Result<()>;
+    /// Floating point and vector registers
+    fn fp_registers(&mut self) -> Result<FpRegisters>;
+    /// Debug register, DR0-DR7
+    fn debug_register(&mut self, n: usize) -> Result<u64>;
+    /// Write debug register
+    fn set_debug_register(&mut self, n: usize, value: u64) -> Result<()>;
     /// Start debugged program
     fn run(&mut self, args: Vec<String>);
+    /// Debug a running process
+    fn attach(&mut self, pid: Pid) -> Result<()>;
+    /// Stop debugging the process, delivering signals to pass, and let it
+    /// run
+    fn detach(&mut self) -> Result<()>;
+    /// Kill the process, and wait for it to be reaped
+    fn kill(&mut self) -> Result<()>;
+    /// Attached to rather than started, so to detach rather than kill
+    fn attached(&self) -> bool;
     /// Read from memory of debugged program
     fn read(&mut self, vaddr: Address, size: usize) -> Result<Vec<u8>>;
     /// Write to memory of debugged program
     fn write(&mut self, vaddr: Address, data: &[u8]) -> Result<usize>;
-    /// Continue program execution
+    /// Continue execution of all threads, until one stops and the others
+    /// are stopped too
     fn cont(&mut self) -> Result<Event>;
-    /// Step one instruction exactly
+    /// Step one instruction exactly in the current thread, keeping the
+    /// others stopped
     fn step(&mut self) -> Result<Event>;
+    /// Threads of the process, as (number, thread ID) in creation order
+    fn threads(&self) -> Vec<(usize, Pid)>;
+    /// Current thread, the one that last stopped unless another was selected
+    fn thread(&self) -> Result<(usize, Pid)>;
+    /// Select the thread registers and memory are accessed through
+    fn select_thread(&mut self, tid: Pid) -> Result<()>;
+    /// Thread that last reported a stop
+    fn stopped_thread(&self) -> Option<Pid>;
+    /// Threads that trapped while being stopped, with the stop unreported
+    fn trapped_threads(&self) -> Vec<Pid>;
+    /// Forget a thread's unreported stop
+    fn discard_stop(&mut self, tid: Pid);
+    /// Deliver a signal to the current thread when it resumes
+    fn pass_signal(&mut self, signal: Signal) -> Result<()>;
+    /// Debug a forked child in place of its parent, which is held stopped
+    fn follow(&mut self, child: Pid) -> Result<()>;
+    /// Restore the original bytes of breakpoints in a process held stopped,
+    /// clear its hardware breakpoints, and detach it or keep holding it
+    fn release(
+        &mut self,
+        pid: Pid,
+        restore: &[(Address, u8)],
+        detach: bool,
+    ) -> Result<()>;
+    /// Hold a vfork(2) parent until the child followed releases its memory,
+    /// with whether to detach it then
+    fn hold_vfork_parent(&mut self, pid: Pid, detach: bool);
+    /// Take the vfork(2) parent held, if any
+    fn take_vfork_parent(&mut self) -> Option<(Pid, bool)>;
+    /// Faulting address of the signal the program last stopped for
+    fn fault_address(&mut self) -> Result<Address>;
 }
 
 /// Target is the common interface for a heterogenous set of traits
@@ -48,18 +115,42 @@ pub trait Target: Debugged + Proc {
 pub enum Event {
     Exited(Pid, i32),
     Stopped,
-    Signal,
+    /// Killed by a signal, and whether it dumped core
+    Terminated(Pid, Signal, bool),
+    /// Stopped by a signal other than a trap
+    Received(Signal),
+    /// No longer debugged, but still running
+    Detached(Pid),
+    /// Killed by the debugger
+    Killed(Pid),
+    /// Forked a child, stopped and traced, and whether by vfork(2)
+    Forked(Pid, bool),
+    /// Executed a new program
+    Exec,
+    /// The vfork(2) child released the parent's memory
+    VforkDone,
 }
 
 /// Interactive debugger type
 impl Debugger {
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let prog = path
-            .as_ref()
-            .canonicalize()
-            .with_context(|_| ErrorKind::path(&path))?;
+    pub fn new(bin: Binary) -> Self {
+        Debugger {
+            bin,
+            target: None,
+            int3s: HashMap::new(),
+            slots: Default::default(),
+        }
+    }
 
-        Ok(Debugger { prog, target: None })
+    /// Return the debugged program's object file
+    pub fn binary(&self) -> &Binary {
+        &self.bin
+    }
+
+    /// Give up the debugged program's object file, e.g. once the process is
+    /// gone
+    pub fn into_binary(self) -> Binary {
+        self.bin
     }
 
     /// Return mutable reference to inner debugged type
@@ -67,24 +158,129 @@ impl Debugger {
         Ok(self.target.as_mut().ok_or(ErrorKind::NotRunning)?)
     }
 
-    /// Set a soft breakpoint and return the replaced byte
-    pub fn set_breakpoint(&mut self, vaddr: Address) -> Result<u8> {
+    /// Insert a soft breakpoint, sharing the INT3 of any other breakpoint at
+    /// the same address
+    pub fn set_breakpoint(&mut self, vaddr: Address) -> Result<()> {
+        if share_int3(&mut self.int3s, vaddr) {
+            return Ok(());
+        }
         let target = self.target()?;
         // Read byte at address
         let bytes = target.read(vaddr, 1)?;
         // Write int3 for soft breakpoint
-        target.write(vaddr, &vec![0xCC])?;
-        Ok(bytes[0])
+        target.write(vaddr, &[0xCC])?;
+        self.int3s.insert(vaddr, (bytes[0], 1));
+        Ok(())
+    }
+
+    /// Remove a soft breakpoint, restoring the saved byte once no other
+    /// breakpoint shares it
+    pub fn remove_breakpoint(&mut self, vaddr: Address) -> Result<()> {
+        match unshare_int3(&mut self.int3s, vaddr) {
+            Some(saved) => self.target()?.write(vaddr, &[saved]).map(|_| ()),
+            None => Ok(()),
+        }
     }
 
-    /// Remove a soft breakpoint, restore saved byte
-    pub fn remove_breakpoint(
+    /// Insert a hardware breakpoint or watchpoint in a free debug register,
+    /// sharing the register of any other for the same range and access
+    pub fn set_hw_breakpoint(
         &mut self,
-        vaddr: Address,
-        saved: u8,
+        addr: Address,
+        len: usize,
+        kind: Kind,
     ) -> Result<()> {
-        self.write(vaddr, &vec![saved])?;
-        Ok(())
+        let slot = Slot { addr, len, kind };
+        let shared = self.slots.iter_mut().flatten().find(|(s, _)| *s == slot);
+        if let Some((_, count)) = shared {
+            *count += 1;
+            return Ok(());
+        }
+        let free = self.slots.iter().position(Option::is_none);
+        let i = free.ok_or(ErrorKind::HardwareLimit)?;
+        self.target()?.set_debug_register(i, addr as u64)?;
+        self.slots[i] = Some((slot, 1));
+        self.write_dr7()
+    }
+
+    /// Remove a hardware breakpoint or watchpoint, freeing its debug
+    /// register once no other shares it
+    pub fn remove_hw_breakpoint(
+        &mut self,
+        addr: Address,
+        len: usize,
+        kind: Kind,
+    ) -> Result<()> {
+        let slot = Slot { addr, len, kind };
+        let i = self.slots.iter().position(|s| match s {
+            Some((s, _)) => *s == slot,
+            None => false,
+        });
+        let i = match i {
+            Some(i) => i,
+            None => return Ok(()),
+        };
+        if let Some((_, count)) = self.slots[i].as_mut() {
+            *count -= 1;
+            if *count > 0 {
+                return Ok(());
+            }
+        }
+        self.slots[i] = None;
+        self.write_dr7()
+    }
+
+    /// Enable the debug registers in use
+    fn write_dr7(&mut self) -> Result<()> {
+        let slots: Vec<_> = self.slots.iter().map(|s| s.map(|s| s.0)).collect();
+        self.target()?.set_debug_register(7, hw::dr7(&slots))
+    }
+
+    /// Deliver a signal the current thread stopped for when it next resumes
+    pub fn pass_signal(&mut self, signal: Signal) -> Result<()> {
+        self.target()?.pass_signal(signal)
+    }
+
+    /// Threads of the process, as (number, thread ID) in creation order
+    pub fn threads(&mut self) -> Result<Vec<(usize, Pid)>> {
+        Ok(self.target()?.threads())
+    }
+
+    /// Current thread, as (number, thread ID)
+    pub fn thread(&mut self) -> Result<(usize, Pid)> {
+        self.target()?.thread()
+    }
+
+    /// Select the thread registers and memory are accessed through
+    pub fn select_thread(&mut self, tid: Pid) -> Result<()> {
+        self.target()?.select_thread(tid)
+    }
+
+    /// Faulting address of the signal the process last stopped for, e.g. the
+    /// memory a SIGSEGV accessed
+    pub fn fault_address(&mut self) -> Result<Address> {
+        self.target()?.fault_address()
+    }
+
+    /// Hardware breakpoints and watchpoints that triggered the last stop,
+    /// clearing their status
+    pub fn hw_hits(&mut self) -> Result<Vec<Slot>> {
+        if self.slots.iter().all(Option::is_none) {
+            return Ok(Vec::new());
+        }
+        let dr6 = self.target()?.debug_register(6)?;
+        self.target()?.set_debug_register(6, 0)?;
+        let hits = hw::hits(dr6).filter_map(|i| self.slots[i]);
+        Ok(hits.map(|(slot, _)| slot).collect())
+    }
+
+    /// Hardware instruction breakpoint on an address, which traps before
+    /// executing it
+    fn hw_breakpoint_at(&self, addr: Address) -> bool {
+        self.slots
+            .iter()
+            .flatten()
+            .any(|(slot, _)| slot.kind == Kind::Hardware && slot.addr == addr)
     }
 
     /// Read program counter of debugged process
@@ -92,41 +288,318 @@ impl Debugger {
         self.target()?.pc()
     }
 
-    /// Read from memory of debugged process
+    /// Read general purpose registers of debugged process
+    pub fn registers(&mut self) -> Result<Registers> {
+        self.target()?.registers()
+    }
+
+    /// Write general purpose registers of debugged process
+    pub fn set_registers(&mut self, regs: &Registers) -> Result<()> {
+        self.target()?.set_registers(regs)
+    }
+
+    /// Read floating point and vector registers of debugged process
+    pub fn fp_registers(&mut self) -> Result<FpRegisters> {
+        self.target()?.fp_registers()
+    }
+
+    /// Read from memory of debugged process, hiding inserted INT3s
     pub fn read(&mut self, vaddr: Address, n: usize) -> Result<Vec<u8>> {
-        self.target()?.read(vaddr, n)
+        let mut data = self.target()?.read(vaddr, n)?;
+        hide_int3s(&self.int3s, vaddr, &mut data);
+        Ok(data)
     }
 
-    /// Write to memory of debugged process
+    /// Write to memory of debugged process, keeping inserted INT3s in place
+    /// and saving the written bytes to restore instead
     pub fn write(&mut self, vaddr: Address, data: &[u8]) -> Result<()> {
-        self.target()?.write(vaddr, data).map(|_| ())
+        let mut data = data.to_vec();
+        keep_int3s(&mut self.int3s, vaddr, &mut data);
+        self.target()?.write(vaddr, &data).map(|_| ())
     }
 
-    /// Continue execution of debugged process
+    /// Continue execution of debugged process, stepping over a breakpoint at
+    /// the program counter first, of the current thread and the one that last
+    /// stopped
     pub fn cont(&mut self) -> Result<Event> {
-        self.target()?.cont()
+        let (_, current) = self.thread()?;
+        let stopped = self.target()?.stopped_thread();
+        let stopped = stopped.filter(|&tid| tid != current);
+        // The current thread last, leaving it selected
+        for tid in stopped.into_iter().chain(Some(current)) {
+            self.select_thread(tid)?;
+            let pc = self.pc()?;
+            if self.int3s.contains_key(&pc) || self.hw_breakpoint_at(pc) {
+                match self.step()? {
+                    Event::Stopped => (),
+                    event => return Ok(event),
+                }
+            }
+        }
+        let event = self.resume_target(false)?;
+        if let Event::Stopped | Event::Received(_) = event {
+            self.cancel_breakpoints()?;
+        }
+        if let Event::Stopped = event {
+            self.rewind()?;
+        }
+        Ok(event)
+    }
+
+    /// Resume the target, putting INT3s back once a vfork child no longer
+    /// shares the memory they were taken out of, and releasing a vfork parent
+    /// once the child debugged instead is gone
+    fn resume_target(&mut self, step: bool) -> Result<Event> {
+        loop {
+            let target = self.target()?;
+            let event = match step {
+                true => target.step()?,
+                false => target.cont()?,
+            };
+            match event {
+                Event::VforkDone => {
+                    let addrs: Vec<Address> =
+                        self.int3s.keys().cloned().collect();
+                    for addr in addrs {
+                        self.target()?.write(addr, &[0xCC])?;
+                    }
+                    if step {
+                        return Ok(Event::Stopped);
+                    }
+                }
+                Event::Exited(..) | Event::Terminated(..) => {
+                    self.release_vfork_parent()?;
+                    return Ok(event);
+                }
+                event => return Ok(event),
+            }
+        }
+    }
+
+    /// Original bytes under the INT3s inserted
+    fn saved_bytes(&self) -> Vec<(Address, u8)> {
+        self.int3s
+            .iter()
+            .map(|(&addr, &(saved, _))| (addr, saved))
+            .collect()
+    }
+
+    /// Debug the child or keep debugging the parent after a fork, taking
+    /// breakpoints out of the other process, and detaching it or holding it
+    /// stopped. A vfork parent sharing memory with the child followed is left
+    /// as is until the child executes a program or exits.
+    pub fn follow_fork(
+        &mut self,
+        child: Pid,
+        vfork: bool,
+        follow_child: bool,
+        detach: bool,
+    ) -> Result<()> {
+        let other = match follow_child {
+            true => {
+                let parent = self.proc()?.pid();
+                self.target()?.follow(child)?;
+                if vfork {
+                    self.target()?.hold_vfork_parent(parent, detach);
+                    return Ok(());
+                }
+                parent
+            }
+            false => child,
+        };
+        let saved = self.saved_bytes();
+        self.target()?.release(other, &saved, detach)
+    }
+
+    /// Release a vfork parent held, now its memory is its own again
+    fn release_vfork_parent(&mut self) -> Result<()> {
+        if let Some((parent, detach)) = self.target()?.take_vfork_parent() {
+            let saved = self.saved_bytes();
+            self.target()?.release(parent, &saved, detach)?;
+        }
+        Ok(())
+    }
+
+    /// Switch to the program the process executed, with no breakpoints
+    /// inserted in its new memory
+    pub fn exec(&mut self, bin: Binary) -> Result<()> {
+        self.release_vfork_parent()?;
+        self.int3s.clear();
+        self.slots = Default::default();
+        self.bin = bin;
+        let bias = self.load_bias()?;
+        self.bin.relocate(bias);
+        Ok(())
+    }
+
+    /// Rewind the program counter past one of our INT3s, returning whether
+    /// it was
+    fn rewind(&mut self) -> Result<bool> {
+        let pc = self.pc()?;
+        let addr = match int3_before(&self.int3s, pc) {
+            Some(addr) => addr,
+            None => return Ok(false),
+        };
+        let mut regs = self.registers()?;
+        regs.set("rip", addr as u64);
+        self.set_registers(&regs)?;
+        Ok(true)
     }
 
-    /// Single step the debugged process
+    /// Back up threads that hit a breakpoint while being stopped, so they hit
+    /// it again when resumed instead of reporting a stale stop
+    fn cancel_breakpoints(&mut self) -> Result<()> {
+        let (_, current) = self.thread()?;
+        for tid in self.target()?.trapped_threads() {
+            self.select_thread(tid)?;
+            if self.rewind()? {
+                self.target()?.discard_stop(tid);
+            }
+        }
+        self.select_thread(current)
+    }
+
+    /// Single step the debugged process, executing the original instruction
+    /// under a breakpoint at the program counter
     pub fn step(&mut self) -> Result<Event> {
-        self.target()?.step()
+        let pc = self.pc()?;
+        let saved = self.int3s.get(&pc).map(|&(saved, _)| saved);
+        if let Some(saved) = saved {
+            self.target()?.write(pc, &[saved])?;
+        }
+        // A hardware breakpoint would trap again before the instruction
+        let hw = self.hw_breakpoint_at(pc);
+        if hw {
+            let slots: Vec<_> = self
+                .slots
+                .iter()
+                .map(|s| s.map(|s| s.0))
+                .map(|s| s.filter(|s| s.kind != Kind::Hardware || s.addr != pc))
+                .collect();
+            self.target()?.set_debug_register(7, hw::dr7(&slots))?;
+        }
+        let event = self.resume_target(true)?;
+        // Re-insert breakpoints unless the process is gone, or its memory
+        // with them
+        if let Event::Stopped | Event::Received(_) | Event::Forked(..) = event {
+            if saved.is_some() {
+                self.target()?.write(pc, &[0xCC])?;
+            }
+            if hw {
+                self.write_dr7()?;
+            }
+        }
+        Ok(event)
     }
 
     /// Run a new debugged process
-    pub fn run(&mut self, args: Vec<String>) {
+    pub fn run(
+        &mut self,
+        args: Vec<String>,
+        disable_randomization: bool,
+    ) -> Result<()> {
         println!(
             "Starting program: {} {}",
-            self.prog.display(),
+            self.bin.path.display(),
             args.join(" "),
         );
 
-        self.target = Some(Ptraced::new(self.prog.clone()));
-        if let Some(target) = self.target.as_mut() {
-            target.run(args.clone());
+        // Breakpoints must be inserted into the new process
+        self.release()?;
+        let mut target =
+            Box::new(Ptraced::new(&self.bin.path, disable_randomization));
+        target.run(args);
+        self.target = Some(target);
+
+        // Symbols and lines move with a position independent executable
+        let bias = self.load_bias()?;
+        self.bin.relocate(bias);
+        Ok(())
+    }
+
+    /// Attach to a running process of the program
+    pub fn attach(&mut self, pid: Pid) -> Result<()> {
+        // Breakpoints must be inserted into the process
+        self.release()?;
+        let mut target = Box::new(Ptraced::new(&self.bin.path, false));
+        target.attach(pid)?;
+        self.target = Some(target);
+
+        let bias = self.load_bias()?;
+        self.bin.relocate(bias);
+        Ok(())
+    }
+
+    /// Detach from the process, removing breakpoints so it runs on as if it
+    /// had never been debugged
+    pub fn detach(&mut self) -> Result<Event> {
+        let pid = self.proc()?.pid();
+        let int3s: Vec<_> = self.int3s.drain().collect();
+        for (addr, (saved, _)) in int3s {
+            self.target()?.write(addr, &[saved])?;
         }
+        self.slots = Default::default();
+        self.write_dr7()?;
+        self.target()?.detach()?;
+        Ok(Event::Detached(pid))
+    }
+
+    /// Kill the process, leaving the debugger ready to run it again
+    pub fn kill(&mut self) -> Result<Event> {
+        let pid = self.proc()?.pid();
+        self.int3s.clear();
+        self.slots = Default::default();
+        self.target()?.kill()?;
+        Ok(Event::Killed(pid))
+    }
+
+    /// Let go of the process, if there is one: detach one attached to, with
+    /// breakpoints removed, or kill one started
+    pub fn release(&mut self) -> Result<()> {
+        let attached = match self.target.as_ref() {
+            Some(target) => target.attached(),
+            None => return Ok(()),
+        };
+        match attached {
+            true => self.detach()?,
+            false => self.kill()?,
+        };
+        self.target = None;
+        Ok(())
+    }
+
+    /// Whether the process was attached to rather than started
+    pub fn attached(&mut self) -> Result<bool> {
+        Ok(self.target()?.attached())
+    }
+
+    /// Load bias of the debugged process's executable, from the entry point
+    /// the kernel passed it, or else the executable's lowest mapping
+    fn load_bias(&mut self) -> Result<Address> {
+        if !self.bin.is_pie {
+            return Ok(0);
+        }
+        let proc = self.proc()?;
+        // The entry point is the program's unless it was run through the
+        // dynamic loader, so check it is mapped from the executable
+        if let Some(entry) = proc.find_auxv(AT_ENTRY)? {
+            let entry = entry as Address;
+            if let Some(map) = proc.find_map(entry)? {
+                if map.pathname == self.bin.path {
+                    let link_entry = self.bin.entry.wrapping_sub(self.bin.bias);
+                    return Ok(entry.wrapping_sub(link_entry));
+                }
+            }
+        }
+        let map = proc
+            .proc_maps()?
+            .into_iter()
+            .find(|map| map.pathname == self.bin.path && map.offset == 0)
+            .ok_or(ErrorKind::LoadBias)?;
+        let link_start = self.bin.address_range().start - self.bin.bias;
+        Ok(map.address_range.start.wrapping_sub(link_start & !0xfff))
     }
 
-    /// Return a /proc reader
     pub fn proc(&mut self) -> Result<Box<dyn ProcReader>> {
         Ok(self.target()?.proc())
     }
@@ -139,33 +612,209 @@ pub struct Breakpoint {
     pub addr: Address,
     /// Breakpoint active flag
     pub enabled: bool,
-    /// Saved instruction byte
-    saved: Option<u8>,
+    /// Description of the location, e.g. "in main at hello.c:4"
+    pub what: String,
+    /// Location not yet resolved to an address, e.g. before a file is loaded
+    pub pending: bool,
+    /// How the breakpoint is inserted
+    pub kind: Kind,
+    /// Bytes watched by a watchpoint
+    pub len: usize,
+    /// Expression to stop only if true
+    pub condition: Option<String>,
+    /// Number of hits to ignore before stopping
+    pub ignore: usize,
+    /// Number of times hit in the current process
+    pub hits: usize,
+    /// Deleted when next hit
+    pub temporary: bool,
+    /// Command lines to run when hit
+    pub commands: Vec<String>,
+    /// Inserted in the current process
+    inserted: bool,
 }
 
 impl Breakpoint {
-    /// Create a new breakpoint for a target address
+    /// Create a new enabled software breakpoint for a target address
     pub fn new(addr: Address) -> Self {
         Breakpoint {
             addr,
-            enabled: false,
-            saved: None,
+            enabled: true,
+            what: String::new(),
+            pending: false,
+            kind: Kind::Software,
+            len: 1,
+            condition: None,
+            ignore: 0,
+            hits: 0,
+            temporary: false,
+            commands: Vec::new(),
+            inserted: false,
+        }
+    }
+
+    /// Create a new watchpoint on a range of memory
+    pub fn watchpoint(addr: Address, len: usize, kind: Kind) -> Self {
+        Breakpoint {
+            kind,
+            len,
+            ..Breakpoint::new(addr)
+        }
+    }
+
+    /// Create a new breakpoint for a location spec yet to be resolved
+    pub fn pending(what: String) -> Self {
+        Breakpoint {
+            what,
+            pending: true,
+            ..Breakpoint::new(0)
         }
     }
 
     /// Enable breakpoint on debugger
     pub fn enable(&mut self, dbg: &mut Debugger) -> Result<()> {
-        self.saved.replace(dbg.set_breakpoint(self.addr)?);
+        if !self.inserted && !self.pending {
+            match self.kind {
+                Kind::Software => dbg.set_breakpoint(self.addr)?,
+                // Checked while single stepping instead
+                Kind::SoftwareWatch => (),
+                kind => dbg.set_hw_breakpoint(self.addr, self.len, kind)?,
+            }
+            self.inserted = true;
+        }
         self.enabled = true;
         Ok(())
     }
 
     /// Disabled breakpoitn on debugger
     pub fn disable(&mut self, dbg: &mut Debugger) -> Result<()> {
-        if let Some(byte) = self.saved {
-            dbg.remove_breakpoint(self.addr, byte)?;
+        if self.inserted {
+            match self.kind {
+                Kind::Software => dbg.remove_breakpoint(self.addr)?,
+                Kind::SoftwareWatch => (),
+                kind => dbg.remove_hw_breakpoint(self.addr, self.len, kind)?,
+            }
+            self.inserted = false;
         }
         self.enabled = false;
         Ok(())
     }
+
+    /// Insert an enabled breakpoint into a newly started process, counting
+    /// hits afresh
+    pub fn arm(&mut self, dbg: &mut Debugger) -> Result<()> {
+        self.inserted = false;
+        self.hits = 0;
+        match self.enabled {
+            true => self.enable(dbg),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Count another breakpoint sharing the INT3 at an address, returning false
+/// if there's none to share yet
+fn share_int3(
+    int3s: &mut HashMap<Address, (u8, usize)>,
+    addr: Address,
+) -> bool {
+    match int3s.get_mut(&addr) {
+        Some((_, count)) => {
+            *count += 1;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Count one less breakpoint sharing the INT3 at an address, returning the
+/// saved byte to restore once none is left
+fn unshare_int3(
+    int3s: &mut HashMap<Address, (u8, usize)>,
+    addr: Address,
+) -> Option<u8> {
+    match int3s.get_mut(&addr) {
+        Some((_, count)) if *count > 1 => {
+            *count -= 1;
+            None
+        }
+        Some(_) => int3s.remove(&addr).map(|(saved, _)| saved),
+        None => None,
+    }
+}
+
+/// Replace INT3s in memory read from an address with the saved bytes
+fn hide_int3s(
+    int3s: &HashMap<Address, (u8, usize)>,
+    vaddr: Address,
+    data: &mut [u8],
+) {
+    for (&addr, &(saved, _)) in int3s.iter() {
+        if vaddr <= addr && addr < vaddr + data.len() {
+            data[addr - vaddr] = saved;
+        }
+    }
+}
+
+/// Keep INT3s in memory written to an address, saving the written bytes to
+/// restore instead
+fn keep_int3s(
+    int3s: &mut HashMap<Address, (u8, usize)>,
+    vaddr: Address,
+    data: &mut [u8],
+) {
+    for (&addr, (saved, _)) in int3s.iter_mut() {
+        if vaddr <= addr && addr < vaddr + data.len() {
+            *saved = std::mem::replace(&mut data[addr - vaddr], 0xCC);
+        }
+    }
+}
+
+/// Address of the INT3 a program counter is just past, if any
+fn int3_before(
+    int3s: &HashMap<Address, (u8, usize)>,
+    pc: Address,
+) -> Option<Address> {
+    pc.checked_sub(1).filter(|addr| int3s.contains_key(addr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_int3() {
+        let mut int3s = HashMap::new();
+        assert!(!share_int3(&mut int3s, 0x1004));
+        int3s.insert(0x1004, (4, 1));
+        assert!(share_int3(&mut int3s, 0x1004));
+        assert_eq!(unshare_int3(&mut int3s, 0x1004), None);
+        assert_eq!(unshare_int3(&mut int3s, 0x1004), Some(4));
+        assert!(int3s.is_empty());
+        assert_eq!(unshare_int3(&mut int3s, 0x1004), None);
+    }
+
+    #[test]
+    fn test_masked_int3s() {
+        let mut int3s = HashMap::new();
+        int3s.insert(0x1004, (4, 1));
+        int3s.insert(0x1008, (8, 2));
+        let mut data = vec![3, 0xCC, 5, 6, 7, 0xCC];
+        hide_int3s(&int3s, 0x1003, &mut data);
+        assert_eq!(data, vec![3, 4, 5, 6, 7, 8]);
+        let mut data = vec![0x90; 4];
+        keep_int3s(&mut int3s, 0x1002, &mut data);
+        assert_eq!(data, vec![0x90, 0x90, 0xCC, 0x90]);
+        assert_eq!(int3s[&0x1004], (0x90, 1));
+        assert_eq!(int3s[&0x1008], (8, 2));
+    }
+
+    #[test]
+    fn test_int3_before() {
+        let mut int3s = HashMap::new();
+        int3s.insert(0x1004, (4, 1));
+        assert_eq!(int3_before(&int3s, 0x1005), Some(0x1004));
+        assert_eq!(int3_before(&int3s, 0x1004), None);
+        assert_eq!(int3_before(&int3s, 0), None);
+    }
 }
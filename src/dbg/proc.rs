@@ -1,10 +1,18 @@
 use std::fmt::Debug;
 use std::ops::Range;
 use std::path::PathBuf;
+use std::str::FromStr;
 
-use super::Result;
+use super::{Address, Error, ErrorKind, Pid, Result};
 
-#[derive(Debug)]
+/// Auxiliary vector terminator type
+const AT_NULL: u64 = 0;
+
+/// Auxiliary vector type of the program's entry point
+pub const AT_ENTRY: u64 = 9;
+
+/// Memory mapping from /proc/PID/maps
+#[derive(Debug, Clone, PartialEq)]
 pub struct Map {
     pub address_range: Range<usize>,
     pub perms: String,
@@ -25,7 +33,97 @@ pub trait Proc: Debug {
 
 /// Read /proc process information a process by PID
 pub trait ProcReader: Debug {
+    /// Process ID
+    fn pid(&self) -> Pid;
+    /// Memory mappings, in address order
     fn proc_maps(&self) -> Result<Vec<Map>>;
+    /// Auxiliary vector passed by the kernel at exec, as (type, value) pairs
+    fn auxv(&self) -> Result<Vec<(u64, u64)>>;
+    /// Name of a thread of the process, as its command name
+    fn thread_name(&self, tid: Pid) -> Result<String>;
+
+    /// Find an auxiliary vector value by type, e.g. AT_ENTRY
+    fn find_auxv(&self, key: u64) -> Result<Option<u64>> {
+        let auxv = self.auxv()?;
+        Ok(auxv.into_iter().find(|&(k, _)| k == key).map(|(_, v)| v))
+    }
+
+    /// Find the mapping containing an address
+    fn find_map(&self, addr: Address) -> Result<Option<Map>> {
+        Ok(self.proc_maps()?.into_iter().find(|map| map.contains(addr)))
+    }
+}
+
+impl Map {
+    /// Check if an address is in the mapped range
+    pub fn contains(&self, addr: Address) -> bool {
+        self.address_range.start <= addr && addr < self.address_range.end
+    }
+
+    /// Size of the mapped range in bytes
+    pub fn size(&self) -> usize {
+        self.address_range.end - self.address_range.start
+    }
+}
+
+/// Parse a line of /proc/PID/maps, e.g.
+/// "00400000-00401000 r--p 00000000 fd:01 1234    /usr/bin/true"
+impl FromStr for Map {
+    type Err = Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let malformed = || Error::from(ErrorKind::ProcFormat(line.to_owned()));
+        let hex =
+            |s: &str| usize::from_str_radix(s, 16).map_err(|_| malformed());
+
+        // The pathname is optional and may contain spaces
+        let mut fields = line.splitn(6, ' ');
+        let mut next = || fields.next().ok_or_else(malformed);
+        let (range, perms, offset, device, inode) =
+            (next()?, next()?, next()?, next()?, next()?);
+        let pathname = fields.next().unwrap_or_default().trim_start();
+
+        let mut range = range.splitn(2, '-');
+        let start = hex(range.next().ok_or_else(malformed)?)?;
+        let end = hex(range.next().ok_or_else(malformed)?)?;
+
+        let mut device = device.splitn(2, ':');
+        let major = hex(device.next().ok_or_else(malformed)?)?;
+        let minor = hex(device.next().ok_or_else(malformed)?)?;
+
+        let flags = perms.as_bytes();
+        if flags.len() != 4 {
+            return Err(malformed());
+        }
+
+        Ok(Map {
+            address_range: start..end,
+            perms: perms.to_owned(),
+            read: flags[0] == b'r',
+            write: flags[1] == b'w',
+            execute: flags[2] == b'x',
+            private: flags[3] == b'p',
+            offset: hex(offset)?,
+            device_major: major as u16,
+            device_minor: minor as u16,
+            inode: inode.parse().map_err(|_| malformed())?,
+            pathname: PathBuf::from(pathname),
+        })
+    }
+}
+
+/// Parse the native-endian (type, value) pairs of /proc/PID/auxv, up to the
+/// AT_NULL terminator
+pub fn parse_auxv(data: &[u8]) -> Vec<(u64, u64)> {
+    let word = |bytes: &[u8]| {
+        let mut word = [0; 8];
+        word.copy_from_slice(bytes);
+        u64::from_ne_bytes(word)
+    };
+    data.chunks_exact(16)
+        .map(|pair| (word(&pair[..8]), word(&pair[8..])))
+        .take_while(|&(key, _)| key != AT_NULL)
+        .collect()
 }
 
 // /// Default implementation returns NotSupported errors
@@ -43,3 +141,53 @@ pub trait ProcReader: Debug {
 //         Box::new(proc::NotSupported)
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_map() {
+        let line = "7f1c2a000000-7f1c2a022000 r-xp 00002000 fd:01 1835019 \
+                    /usr/lib/x86_64-linux-gnu/ld-linux-x86-64.so.2";
+        let map: Map = line.parse().unwrap();
+        assert_eq!(map.address_range, 0x7f1c2a000000..0x7f1c2a022000);
+        assert_eq!(map.perms, "r-xp");
+        assert!(map.read && !map.write && map.execute && map.private);
+        assert_eq!(map.offset, 0x2000);
+        assert_eq!((map.device_major, map.device_minor), (0xfd, 0x01));
+        assert_eq!(map.inode, 1835019);
+        assert_eq!(
+            map.pathname,
+            PathBuf::from("/usr/lib/x86_64-linux-gnu/ld-linux-x86-64.so.2")
+        );
+        assert!(map.contains(0x7f1c2a021fff));
+        assert!(!map.contains(0x7f1c2a022000));
+    }
+
+    #[test]
+    fn test_parse_map_anonymous() {
+        let map: Map = "7ffd4c3de000-7ffd4c3ff000 rw-p 00000000 00:00 0 \
+                        [stack]"
+            .parse()
+            .unwrap();
+        assert_eq!(map.pathname, PathBuf::from("[stack]"));
+
+        let map: Map = "7f1c2a03c000-7f1c2a03d000 rw-p 00000000 00:00 0"
+            .parse()
+            .unwrap();
+        assert_eq!(map.pathname, PathBuf::new());
+        assert!("7f1c2a03c000 rw-p 00000000 00:00 0".parse::<Map>().is_err());
+    }
+
+    #[test]
+    fn test_parse_auxv() {
+        let words: [u64; 6] = [AT_ENTRY, 0x555555555040, 6, 4096, 0, 0];
+        let data: Vec<u8> =
+            words.iter().flat_map(|word| word.to_ne_bytes()).collect();
+        assert_eq!(
+            parse_auxv(&data),
+            vec![(AT_ENTRY, 0x555555555040), (6, 4096)]
+        );
+    }
+}
@@ -3,22 +3,91 @@ use std::path::Path;
 
 use nix::sys::{
     ptrace,
-    signal::Signal,
-    wait::{waitpid, WaitStatus},
+    signal::{self, Signal},
+    wait::{waitpid, WaitPidFlag, WaitStatus},
 };
 use nix::unistd::{execvp, fork, ForkResult, Pid};
 
+use failure::ResultExt;
+use indexmap::IndexMap;
+
 use super::{
-    proc::{Map, Proc, ProcReader},
+    proc::{self, Map, Proc, ProcReader},
+    regs::{FpRegisters, Registers},
     Address, Debugged, ErrorKind, Event, Result, Target,
 };
 
+/// Note type of the XSAVE area for PTRACE_GETREGSET
+const NT_X86_XSTATE: libc::c_int = 0x202;
+
+/// Offset of the upper ymm halves in the XSAVE area
+const XSAVE_YMMH_OFFSET: usize = 576;
+
+/// Offset of u_debugreg in the user area, struct user of sys/user.h
+const DEBUGREG_OFFSET: usize = 848;
+
+/// Personality flag to disable address space layout randomization
+const ADDR_NO_RANDOMIZE: libc::c_ulong = 0x0040000;
+
 /// Debugging interface for platforms that support ptrace (2)
 #[derive(Debug)]
 pub struct Ptraced {
     prog: CString,
     pid: Option<Pid>,
     status: Option<WaitStatus>,
+    // Run without address space layout randomization
+    disable_randomization: bool,
+    // Attached to rather than started, so detach rather than kill
+    attached: bool,
+    // Threads of the process in creation order, by thread ID
+    threads: IndexMap<Pid, Thread>,
+    // Thread that registers and memory are accessed through
+    current: Option<Pid>,
+    // Thread that last reported a stop
+    stopped: Option<Pid>,
+    // Number of the next new thread
+    next_thread: usize,
+    // Debug registers, which every thread needs a copy of
+    debug_registers: [u64; 8],
+    // Single stepping the current thread, with the others kept stopped
+    stepping: bool,
+    // Other traced processes by process ID, with their threads: forked
+    // children yet to be followed or released, and processes held stopped
+    forks: IndexMap<Pid, IndexMap<Pid, Thread>>,
+    // Vfork parent sharing memory with the process, to release once it
+    // executes or exits, and whether to detach it then
+    vfork_parent: Option<(Pid, bool)>,
+    // Vfork child sharing memory with the process, until it executes or exits
+    vfork_child: Option<Pid>,
+}
+
+/// Thread of the debugged process
+#[derive(Debug)]
+struct Thread {
+    num: usize,
+    // Stopped, rather than running or yet to report a stop
+    stopped: bool,
+    // A SIGSTOP is on its way, to be swallowed when it's reported
+    sigstop: bool,
+    // New, so waiting for its debug registers
+    new: bool,
+    // Stop reported while stopping all threads, to report on resuming
+    pending: Option<WaitStatus>,
+    // Signal to deliver when the thread resumes
+    signal: Option<Signal>,
+}
+
+impl Thread {
+    fn new(num: usize, stopped: bool) -> Self {
+        Thread {
+            num,
+            stopped,
+            sigstop: !stopped,
+            new: true,
+            pending: None,
+            signal: None,
+        }
+    }
 }
 
 impl Target for Ptraced {
@@ -32,32 +101,354 @@ impl Proc for Ptraced {
 }
 
 impl ProcReader for Pid {
+    fn pid(&self) -> super::Pid {
+        self.as_raw() as super::Pid
+    }
+
     fn proc_maps(&self) -> Result<Vec<Map>> {
-        std::process::Command::new("/bin/cat")
-            .arg(format!("/proc/{}/maps", self))
-            .status()
-            .expect("command failed");
-        Ok(Vec::new())
+        let path = format!("/proc/{}/maps", self);
+        let maps = std::fs::read_to_string(&path)
+            .with_context(|_| ErrorKind::path(&path))?;
+        maps.lines().map(str::parse).collect()
+    }
+
+    fn auxv(&self) -> Result<Vec<(u64, u64)>> {
+        let path = format!("/proc/{}/auxv", self);
+        let data =
+            std::fs::read(&path).with_context(|_| ErrorKind::path(&path))?;
+        Ok(proc::parse_auxv(&data))
+    }
+
+    fn thread_name(&self, tid: super::Pid) -> Result<String> {
+        let path = format!("/proc/{}/task/{}/comm", self, tid);
+        let name = std::fs::read_to_string(&path)
+            .with_context(|_| ErrorKind::path(&path))?;
+        Ok(name.trim_end().to_owned())
     }
 }
 
 impl Ptraced {
-    pub fn new<P: AsRef<Path>>(path: P) -> Box<dyn Target> {
+    pub fn new<P: AsRef<Path>>(path: P, disable_randomization: bool) -> Self {
         let prog = CString::new(path.as_ref().to_str().unwrap())
             .expect("null byte in string");
-        Box::new(Ptraced {
+        Ptraced {
             prog,
             pid: None,
             status: None,
-        })
+            disable_randomization,
+            attached: false,
+            threads: IndexMap::new(),
+            current: None,
+            stopped: None,
+            next_thread: 1,
+            debug_registers: [0; 8],
+            stepping: false,
+            forks: IndexMap::new(),
+            vfork_parent: None,
+            vfork_child: None,
+        }
     }
 
     fn pid(&self) -> Result<Pid> {
         Ok(self.pid.ok_or(ErrorKind::NotRunning)?)
     }
 
+    /// Current thread, which registers and memory are accessed through
+    fn tid(&self) -> Result<Pid> {
+        Ok(self.current.ok_or(ErrorKind::NotRunning)?)
+    }
+
+    /// Attach to a thread, waiting for it to stop
+    fn attach_thread(&mut self, tid: Pid) -> nix::Result<()> {
+        ptrace::attach(tid)?;
+
+        // Wait for the SIGSTOP that PTRACE_ATTACH sends
+        let status = waitpid(tid, Some(WaitPidFlag::__WALL))?;
+
+        // Trace the threads and processes it creates, but leave it running
+        // if the tracer exits
+        ptrace::setoptions(tid, trace_options())?;
+
+        self.add_thread(tid, true);
+        let thread = self.threads.get_mut(&tid).expect("thread");
+        if let WaitStatus::Stopped(_, Signal::SIGSTOP) = status {
+            return Ok(());
+        }
+        thread.pending = Some(status);
+        thread.sigstop = true;
+        Ok(())
+    }
+
+    /// Track a new thread, stopped or yet to report its first stop
+    fn add_thread(&mut self, tid: Pid, stopped: bool) {
+        insert_thread(&mut self.threads, &mut self.next_thread, tid, stopped);
+        if self.pid != Some(tid) {
+            println!("[New LWP {}]", tid);
+        }
+    }
+
+    /// Track a forked child, stopped by its first SIGSTOP
+    fn add_fork(&mut self, child: Pid) {
+        let mut threads = IndexMap::new();
+        threads.insert(child, Thread::new(1, true));
+        self.forks.insert(child, threads);
+    }
+
+    /// Thread of the process, rather than a process it forked
+    fn is_thread(&self, tid: Pid) -> Result<bool> {
+        let path = format!("/proc/{}/task/{}", self.pid()?, tid);
+        Ok(Path::new(&path).exists())
+    }
+
+    /// Forget a thread that exited, or the process along with its threads
+    fn remove_thread(&mut self, tid: Pid) {
+        if self.pid == Some(tid) {
+            self.pid = None;
+            self.current = None;
+            self.threads.clear();
+            return;
+        }
+        if remove_thread(&mut self.threads, tid) {
+            println!("[LWP {} exited]", tid);
+        }
+        if self.current == Some(tid) {
+            self.current = self.pid;
+        }
+        if self.stopped == Some(tid) {
+            self.stopped = None;
+        }
+    }
+
+    /// A thread stopped, giving a new one the debug registers the others
+    /// have
+    fn thread_stopped(&mut self, tid: Pid) -> Result<()> {
+        let thread = self.threads.get_mut(&tid).expect("thread");
+        thread.stopped = true;
+        if !std::mem::replace(&mut thread.new, false) {
+            return Ok(());
+        }
+        if self.debug_registers.iter().all(|&value| value == 0) {
+            return Ok(());
+        }
+        for n in (0..4).chain(Some(7)) {
+            poke_debug_register(tid, n, self.debug_registers[n])?;
+        }
+        Ok(())
+    }
+
+    /// Resume a stopped thread, delivering its signal
+    fn resume(&mut self, tid: Pid) -> Result<()> {
+        let thread = self.threads.get_mut(&tid).expect("thread");
+        let signal = thread.signal.take();
+        thread.stopped = false;
+        let resumed = match self.stepping && self.current == Some(tid) {
+            true => ptrace::step(tid, signal),
+            false => ptrace::cont(tid, signal),
+        };
+        Ok(resumed.map_err(|_| ErrorKind::ProcessEvent)?)
+    }
+
+    /// Stop all running threads, keeping any stop they report other than our
+    /// SIGSTOP to report when resuming
+    fn stop_all(&mut self) -> Result<()> {
+        let pid = self.pid()?;
+        loop {
+            let running: Vec<Pid> = self
+                .threads
+                .iter()
+                .filter(|(_, thread)| !thread.stopped)
+                .map(|(tid, _)| *tid)
+                .collect();
+            if running.is_empty() {
+                return Ok(());
+            }
+            for tid in running.iter() {
+                let thread = self.threads.get_mut(tid).expect("thread");
+                if !thread.sigstop {
+                    tgkill(pid, *tid, Signal::SIGSTOP)?;
+                    thread.sigstop = true;
+                }
+            }
+            for tid in running {
+                let status = waitpid(tid, Some(WaitPidFlag::__WALL))
+                    .map_err(|_| ErrorKind::ProcessEvent)?;
+                let thread = self.threads.get_mut(&tid).expect("thread");
+                match status {
+                    WaitStatus::Stopped(_, Signal::SIGSTOP) => {
+                        thread.sigstop = false;
+                    }
+                    status => thread.pending = Some(status),
+                }
+                self.thread_stopped(tid)?;
+            }
+        }
+    }
+
+    /// Wait for the next stop of any thread to report, stopping the others
+    fn wait(&mut self) -> Result<Event> {
+        loop {
+            let pending = match self.stepping {
+                true => None,
+                false => take_pending(&mut self.threads),
+            };
+            let status = match pending {
+                Some(status) => status,
+                None => waitpid(None, Some(WaitPidFlag::__WALL))
+                    .map_err(|_| ErrorKind::ProcessEvent)?,
+            };
+            if let Some(event) = self.dispatch(status)? {
+                self.status = Some(status);
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Handle a thread's wait status, returning the event to report, if any,
+    /// or otherwise resuming the thread
+    fn dispatch(&mut self, status: WaitStatus) -> Result<Option<Event>> {
+        let tid = match status.pid() {
+            Some(tid) => tid,
+            None => return Ok(None),
+        };
+        let event = match status {
+            // Processes held stopped don't run, but can be killed
+            WaitStatus::Exited(..) | WaitStatus::Signaled(..)
+                if self.forks.contains_key(&tid) =>
+            {
+                self.forks.remove(&tid);
+                return Ok(None);
+            }
+            WaitStatus::Exited(_, code) => match self.pid == Some(tid) {
+                true => Event::Exited(tid.as_raw() as usize, code),
+                false => {
+                    self.remove_thread(tid);
+                    return Ok(None);
+                }
+            },
+            WaitStatus::Signaled(_, signal, core) => {
+                match self.pid == Some(tid) {
+                    true => {
+                        Event::Terminated(tid.as_raw() as usize, signal, core)
+                    }
+                    false => {
+                        self.remove_thread(tid);
+                        return Ok(None);
+                    }
+                }
+            }
+            _ => {
+                // A new thread can report its first stop before the clone,
+                // and a forked child before the fork
+                if self.forks.contains_key(&tid) {
+                    return Ok(None);
+                }
+                if !self.threads.contains_key(&tid) {
+                    match self.is_thread(tid)? {
+                        true => self.add_thread(tid, false),
+                        false => {
+                            self.add_fork(tid);
+                            return Ok(None);
+                        }
+                    }
+                }
+                match status {
+                    WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_CLONE) => {
+                        let new = ptrace::getevent(tid)
+                            .map_err(|_| ErrorKind::ProcessEvent)?;
+                        let new = Pid::from_raw(new as libc::pid_t);
+                        if !self.threads.contains_key(&new) {
+                            self.add_thread(new, false);
+                        }
+                        self.thread_stopped(tid)?;
+                        self.resume(tid)?;
+                        return Ok(None);
+                    }
+                    WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_FORK) => {
+                        Event::Forked(self.forked(tid)?, false)
+                    }
+                    WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_VFORK) => {
+                        let child = self.forked(tid)?;
+                        self.vfork_child =
+                            Some(Pid::from_raw(child as libc::pid_t));
+                        Event::Forked(child, true)
+                    }
+                    WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_EXEC) => {
+                        // Other threads are gone, and the one that executed
+                        // the program took the main thread's ID
+                        let pid = self.pid;
+                        self.threads.retain(|other, _| Some(*other) == pid);
+                        self.debug_registers = [0; 8];
+                        Event::Exec
+                    }
+                    WaitStatus::PtraceEvent(
+                        _,
+                        _,
+                        libc::PTRACE_EVENT_VFORK_DONE,
+                    ) => {
+                        self.vfork_child = None;
+                        Event::VforkDone
+                    }
+                    WaitStatus::Stopped(_, Signal::SIGSTOP)
+                        if self.threads[&tid].sigstop =>
+                    {
+                        self.threads[&tid].sigstop = false;
+                        self.thread_stopped(tid)?;
+                        self.resume(tid)?;
+                        return Ok(None);
+                    }
+                    // Rewinding past a breakpoint INT3 is left to the
+                    // debugger, which knows where its breakpoints are inserted
+                    WaitStatus::Stopped(_, Signal::SIGTRAP) => Event::Stopped,
+                    WaitStatus::Stopped(_, signal) => Event::Received(signal),
+                    status => {
+                        Err(ErrorKind::WaitStatus(format!("{:?}", status)))?
+                    }
+                }
+            }
+        };
+
+        match event {
+            Event::Exited(..) | Event::Terminated(..) => {
+                self.remove_thread(tid)
+            }
+            _ => {
+                // All-stop, with the thread that stopped selected
+                self.thread_stopped(tid)?;
+                self.current = Some(tid);
+                self.stopped = Some(tid);
+                self.stop_all()?;
+            }
+        }
+        Ok(Some(event))
+    }
+
+    /// A vfork parent can't run until its child is gone or executes a new
+    /// program, so resuming it with the child held stopped would hang
+    fn check_vfork_child(&self) -> Result<()> {
+        match self.vfork_child {
+            Some(child) if self.forks.contains_key(&child) => {
+                Err(ErrorKind::VforkHeld)?
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Take the child a thread forked, waiting for its first stop unless it
+    /// was already reported
+    fn forked(&mut self, tid: Pid) -> Result<super::Pid> {
+        let child =
+            ptrace::getevent(tid).map_err(|_| ErrorKind::ProcessEvent)?;
+        let child = Pid::from_raw(child as libc::pid_t);
+        if !self.forks.contains_key(&child) {
+            waitpid(child, Some(WaitPidFlag::__WALL))
+                .map_err(|_| ErrorKind::ProcessEvent)?;
+            self.add_fork(child);
+        }
+        Ok(child.as_raw() as super::Pid)
+    }
+
     fn read_word(&mut self, addr: Address) -> Result<[u8; 8]> {
-        Ok(ptrace::read(self.pid()?, addr as ptrace::AddressType)
+        Ok(ptrace::read(self.tid()?, addr as ptrace::AddressType)
             .map(|word| word.to_le_bytes())
             .map_err(|_| ErrorKind::Read(addr))?)
     }
@@ -65,52 +456,54 @@ impl Ptraced {
     fn write_word(&mut self, addr: Address, word: [u8; 8]) -> Result<()> {
         let word = Address::from_le_bytes(word) as ptrace::AddressType;
         Ok(
-            ptrace::write(self.pid()?, addr as ptrace::AddressType, word)
+            ptrace::write(self.tid()?, addr as ptrace::AddressType, word)
                 .map_err(|_| ErrorKind::Write(addr))?,
         )
     }
 
-    fn wait(&mut self) -> Result<Event> {
-        let pid = self.pid()?;
-
-        let status = waitpid(pid, None).expect("waitpid failed");
-
-        let event = match status {
-            WaitStatus::Exited(pid, code) => {
-                self.pid = None;
-                Event::Exited(pid.as_raw() as usize, code)
-            }
-            WaitStatus::Signaled(..) => Event::Signal,
-            WaitStatus::Stopped(_, Signal::SIGTRAP) => {
-                // TODO: we should decrement in the event receiver, dbg.rs,
-                // where we can match a user-defined breakpoint with this INT3
-                // Decrement program counter
-                let mut regs = ptrace::getregs(pid).unwrap();
-                let word = self.read_word((regs.rip - 1) as usize)?;
-                if word[0] == 0xCC {
-                    regs.rip -= 1;
-                    ptrace::setregs(pid, regs).expect("setregs failed");
-                }
-                Event::Stopped
-            }
-            status => {
-                dbg!(status);
-                Err(ErrorKind::ProcessEvent)?
-            }
+    /// Read the XSAVE area's upper ymm halves, if the processor has AVX
+    fn ymmh(&self) -> Result<Option<[u128; 16]>> {
+        let pid = self.tid()?;
+        let mut xsave = [0u8; 4096];
+        let mut iov = libc::iovec {
+            iov_base: xsave.as_mut_ptr() as *mut libc::c_void,
+            iov_len: xsave.len(),
+        };
+        let ret = unsafe {
+            libc::ptrace(
+                libc::PTRACE_GETREGSET,
+                pid.as_raw(),
+                NT_X86_XSTATE,
+                &mut iov as *mut libc::iovec,
+            )
         };
+        if ret < 0 || iov.iov_len < XSAVE_YMMH_OFFSET + 256 {
+            return Ok(None);
+        }
 
-        self.status = Some(status);
-        Ok(event)
+        let mut ymmh = [0; 16];
+        let area = &xsave[XSAVE_YMMH_OFFSET..XSAVE_YMMH_OFFSET + 256];
+        for (half, bytes) in ymmh.iter_mut().zip(area.chunks_exact(16)) {
+            let mut word = [0; 16];
+            word.copy_from_slice(bytes);
+            *half = u128::from_le_bytes(word);
+        }
+        Ok(Some(ymmh))
     }
 }
 
 impl Drop for Ptraced {
     fn drop(&mut self) {
-        if let Some(child) = self.pid {
-            if let Err(e) = ptrace::kill(child) {
-                eprintln!("error: kill: {} ({})", e, child);
+        if self.attached {
+            if let Err(e) = self.detach() {
+                eprintln!("error: detach: {}", e);
+            }
+            return;
+        }
+        if self.pid.is_some() || !self.forks.is_empty() {
+            if let Err(e) = self.kill() {
+                eprintln!("error: kill: {}", e);
             }
-            dbg!(waitpid(child, None).expect("waitpid"));
         }
     }
 }
@@ -130,22 +523,31 @@ impl Debugged for Ptraced {
                 // Initiate a trace with ptrace(PTRACE_TRACEME, ...)
                 ptrace::traceme().expect("ptrace failed");
 
+                // Load at the same addresses every run, like gdb
+                if self.disable_randomization {
+                    unsafe {
+                        let persona = libc::personality(0xffff_ffff);
+                        libc::personality(
+                            persona as libc::c_ulong | ADDR_NO_RANDOMIZE,
+                        );
+                    }
+                }
+
                 // Execute PROG with [ARGS]
                 execvp(&self.prog, &args).expect("execvp failed");
             }
             ForkResult::Parent { child } => {
                 self.pid = Some(child);
+                self.current = Some(child);
+                self.add_thread(child, true);
 
                 // Wait for PTRACE_TRACEME in child
                 let _ = waitpid(child, None).expect("waitpid failed");
 
                 // Terminate tracee if the tracer exits
-                ptrace::setoptions(child, ptrace::Options::PTRACE_O_EXITKILL)
-                    .expect("ptrace failed");
-
-                // Stop the tracee on the next clone(2)
-                ptrace::setoptions(child, ptrace::Options::PTRACE_O_TRACECLONE)
-                    .expect("ptrace failed");
+                let options =
+                    trace_options() | ptrace::Options::PTRACE_O_EXITKILL;
+                ptrace::setoptions(child, options).expect("ptrace failed");
 
                 // Wait for clone(2) event, tracee should stop execution at
                 // _start, which is likely actually _start inside ld.so(8) for
@@ -157,15 +559,293 @@ impl Debugged for Ptraced {
         }
     }
 
+    fn attach(&mut self, pid: super::Pid) -> Result<()> {
+        let pid = Pid::from_raw(pid as libc::pid_t);
+        self.pid = Some(pid);
+        if let Err(e) = self.attach_thread(pid) {
+            self.pid = None;
+            Err(match e {
+                nix::Error::Sys(errno) => {
+                    ErrorKind::Attach(errno.desc().into())
+                }
+                e => ErrorKind::Attach(e.to_string()),
+            })?
+        }
+        self.current = Some(pid);
+        self.attached = true;
+
+        // Attach to the other threads, again for any they start meanwhile
+        loop {
+            let path = format!("/proc/{}/task", pid);
+            let tasks = std::fs::read_dir(&path)
+                .with_context(|_| ErrorKind::path(&path))?;
+            let tids: Vec<Pid> = tasks
+                .filter_map(|entry| {
+                    entry.ok()?.file_name().to_str()?.parse().ok()
+                })
+                .map(Pid::from_raw)
+                .filter(|tid| !self.threads.contains_key(tid))
+                .collect();
+            if tids.is_empty() {
+                return Ok(());
+            }
+            for tid in tids {
+                // Threads exiting meanwhile can't be attached to
+                let _ = self.attach_thread(tid);
+            }
+        }
+    }
+
+    fn detach(&mut self) -> Result<()> {
+        // Processes held stopped go too, their breakpoints already removed
+        let forks = std::mem::replace(&mut self.forks, IndexMap::new());
+        let threads = std::mem::replace(&mut self.threads, IndexMap::new());
+        for threads in Some(threads)
+            .into_iter()
+            .chain(forks.into_iter().map(|(_, t)| t))
+        {
+            for (tid, thread) in threads {
+                detach_thread(tid, thread)?;
+            }
+        }
+        self.vfork_parent = None;
+        self.vfork_child = None;
+        self.pid = None;
+        self.current = None;
+        self.threads.clear();
+        self.attached = false;
+        Ok(())
+    }
+
+    fn attached(&self) -> bool {
+        self.attached
+    }
+
+    fn kill(&mut self) -> Result<()> {
+        // Processes held stopped go too
+        let mut pids: Vec<Pid> = self.forks.keys().cloned().collect();
+        pids.extend(self.pid);
+        if pids.is_empty() {
+            Err(ErrorKind::NotRunning)?
+        }
+        for pid in pids.iter() {
+            signal::kill(*pid, Signal::SIGKILL)
+                .map_err(|_| ErrorKind::ProcessEvent)?;
+        }
+
+        // Reap every thread, the main ones last, passing over any stops
+        // reported before SIGKILL took effect
+        while !pids.is_empty() {
+            match waitpid(None, Some(WaitPidFlag::__WALL)) {
+                Ok(WaitStatus::Exited(tid, _))
+                | Ok(WaitStatus::Signaled(tid, _, _)) => {
+                    pids.retain(|pid| *pid != tid)
+                }
+                Ok(_) => continue,
+                Err(_) => break,
+            }
+        }
+        self.forks.clear();
+        self.vfork_parent = None;
+        self.vfork_child = None;
+        self.pid = None;
+        self.current = None;
+        self.threads.clear();
+        self.status = None;
+        self.attached = false;
+        Ok(())
+    }
+
+    fn follow(&mut self, child: super::Pid) -> Result<()> {
+        let child = Pid::from_raw(child as libc::pid_t);
+        let threads = self
+            .forks
+            .remove(&child)
+            .ok_or(ErrorKind::Thread(child.as_raw() as super::Pid))?;
+        let parent = std::mem::replace(&mut self.threads, threads);
+        self.forks.insert(self.pid()?, parent);
+        self.pid = Some(child);
+        self.vfork_child = None;
+        self.current = Some(child);
+        self.stopped = None;
+        self.next_thread = 2;
+
+        // A forked child has none of its parent's debug registers
+        for n in (0..4).chain(Some(7)) {
+            poke_debug_register(child, n, self.debug_registers[n])?;
+        }
+        Ok(())
+    }
+
+    fn release(
+        &mut self,
+        pid: super::Pid,
+        restore: &[(Address, u8)],
+        detach: bool,
+    ) -> Result<()> {
+        let pid = Pid::from_raw(pid as libc::pid_t);
+        let threads = self
+            .forks
+            .get(&pid)
+            .ok_or(ErrorKind::Thread(pid.as_raw() as super::Pid))?;
+
+        // Breakpoints the process shares would trap with no one to handle it
+        for &(addr, byte) in restore {
+            poke_byte(pid, addr, byte)?;
+        }
+        for tid in threads.keys() {
+            poke_debug_register(*tid, 7, 0)?;
+        }
+        if !detach {
+            return Ok(());
+        }
+        let threads = self.forks.remove(&pid).expect("fork");
+        for (tid, thread) in threads {
+            detach_thread(tid, thread)?;
+        }
+        Ok(())
+    }
+
+    fn hold_vfork_parent(&mut self, pid: super::Pid, detach: bool) {
+        self.vfork_parent = Some((Pid::from_raw(pid as libc::pid_t), detach));
+    }
+
+    fn take_vfork_parent(&mut self) -> Option<(super::Pid, bool)> {
+        let (pid, detach) = self.vfork_parent.take()?;
+        Some((pid.as_raw() as super::Pid, detach))
+    }
+
+    fn threads(&self) -> Vec<(usize, super::Pid)> {
+        self.threads
+            .iter()
+            .map(|(tid, thread)| (thread.num, tid.as_raw() as super::Pid))
+            .collect()
+    }
+
+    fn thread(&self) -> Result<(usize, super::Pid)> {
+        let tid = self.tid()?;
+        Ok((self.threads[&tid].num, tid.as_raw() as super::Pid))
+    }
+
+    fn select_thread(&mut self, tid: super::Pid) -> Result<()> {
+        let tid = Pid::from_raw(tid as libc::pid_t);
+        if !self.threads.contains_key(&tid) {
+            Err(ErrorKind::Thread(tid.as_raw() as super::Pid))?
+        }
+        self.current = Some(tid);
+        Ok(())
+    }
+
+    fn stopped_thread(&self) -> Option<super::Pid> {
+        self.stopped.map(|tid| tid.as_raw() as super::Pid)
+    }
+
+    fn trapped_threads(&self) -> Vec<super::Pid> {
+        trapped_threads(&self.threads)
+            .into_iter()
+            .map(|tid| tid.as_raw() as super::Pid)
+            .collect()
+    }
+
+    fn discard_stop(&mut self, tid: super::Pid) {
+        let tid = Pid::from_raw(tid as libc::pid_t);
+        if let Some(thread) = self.threads.get_mut(&tid) {
+            thread.pending = None;
+        }
+    }
+
+    fn pass_signal(&mut self, signal: Signal) -> Result<()> {
+        let tid = self.tid()?;
+        self.threads[&tid].signal = Some(signal);
+        Ok(())
+    }
+
     fn pc(&mut self) -> Result<Address> {
-        let pid = self.pid()?;
-        let registers = ptrace::getregs(pid).expect("ptrace failed");
-        Ok(registers.rip as usize)
+        let regs =
+            ptrace::getregs(self.tid()?).map_err(|_| ErrorKind::Registers)?;
+        Ok(regs.rip as usize)
+    }
+
+    fn registers(&mut self) -> Result<Registers> {
+        let regs =
+            ptrace::getregs(self.tid()?).map_err(|_| ErrorKind::Registers)?;
+        Ok(Registers { regs })
+    }
+
+    fn set_registers(&mut self, regs: &Registers) -> Result<()> {
+        ptrace::setregs(self.tid()?, regs.regs)
+            .map_err(|_| ErrorKind::Registers)?;
+        Ok(())
+    }
+
+    fn fp_registers(&mut self) -> Result<FpRegisters> {
+        let pid = self.tid()?;
+        let mut regs =
+            std::mem::MaybeUninit::<libc::user_fpregs_struct>::uninit();
+        let ret = unsafe {
+            libc::ptrace(
+                libc::PTRACE_GETFPREGS,
+                pid.as_raw(),
+                std::ptr::null_mut::<libc::c_void>(),
+                regs.as_mut_ptr(),
+            )
+        };
+        if ret < 0 {
+            Err(ErrorKind::Registers)?
+        }
+        let regs = unsafe { regs.assume_init() };
+        Ok(FpRegisters {
+            regs,
+            ymmh: self.ymmh()?,
+        })
+    }
+
+    fn debug_register(&mut self, n: usize) -> Result<u64> {
+        let pid = self.tid()?;
+        let offset = DEBUGREG_OFFSET + n * 8;
+        // PEEKUSER returns the word, so only errno tells -1 from an error
+        let ret = unsafe {
+            nix::errno::Errno::clear();
+            libc::ptrace(
+                libc::PTRACE_PEEKUSER,
+                pid.as_raw(),
+                offset as *mut libc::c_void,
+                std::ptr::null_mut::<libc::c_void>(),
+            )
+        };
+        if ret == -1 && nix::errno::errno() != 0 {
+            Err(ErrorKind::Registers)?
+        }
+        Ok(ret as u64)
+    }
+
+    fn set_debug_register(&mut self, n: usize, value: u64) -> Result<()> {
+        // Breakpoints and watchpoints are for every thread, but hits are
+        // cleared for the one that hit them
+        if n == 6 {
+            return poke_debug_register(self.tid()?, n, value);
+        }
+        self.debug_registers[n] = value;
+        let tids: Vec<Pid> = self.threads.keys().cloned().collect();
+        for tid in tids {
+            poke_debug_register(tid, n, value)?;
+        }
+        Ok(())
     }
 
     fn cont(&mut self) -> Result<Event> {
-        let pid = self.pid()?;
-        ptrace::cont(pid, None).unwrap();
+        self.check_vfork_child()?;
+        self.stepping = false;
+        // Threads with a stop to report stay stopped to report it
+        let tids: Vec<Pid> = self
+            .threads
+            .iter()
+            .filter(|(_, thread)| thread.stopped && thread.pending.is_none())
+            .map(|(tid, _)| *tid)
+            .collect();
+        for tid in tids {
+            self.resume(tid)?;
+        }
         self.wait()
     }
 
@@ -197,7 +877,7 @@ impl Debugged for Ptraced {
         }
 
         // Write partial word value
-        let mut iter = iter.remainder().iter().rev();
+        let mut iter = iter.remainder().iter();
         let mut j = 0;
         if iter.len() > 0 {
             let mut word = self.read(vaddr + (i * word_size), word_size)?;
@@ -208,12 +888,226 @@ impl Debugged for Ptraced {
             self.write(vaddr + (i * word_size), &word)?;
         }
 
-        Ok((i + j) * word_size)
+        Ok(i * word_size + j)
     }
 
     fn step(&mut self) -> Result<Event> {
-        let pid = self.pid()?;
-        ptrace::step(pid, None).expect("ptrace single step failed");
-        self.wait()
+        self.check_vfork_child()?;
+        let tid = self.tid()?;
+        self.stepping = true;
+        self.resume(tid)?;
+        let event = self.wait();
+        self.stepping = false;
+        event
+    }
+
+    fn fault_address(&mut self) -> Result<Address> {
+        fault_address(self.tid()?)
+    }
+}
+
+/// Track a new thread under the next number, which numbers of exited threads
+/// aren't reused for
+fn insert_thread(
+    threads: &mut IndexMap<Pid, Thread>,
+    next: &mut usize,
+    tid: Pid,
+    stopped: bool,
+) {
+    threads.insert(tid, Thread::new(*next, stopped));
+    *next += 1;
+}
+
+/// Forget a thread, keeping the others in creation order. Returns false if
+/// it wasn't tracked.
+fn remove_thread(threads: &mut IndexMap<Pid, Thread>, tid: Pid) -> bool {
+    if !threads.contains_key(&tid) {
+        return false;
+    }
+    threads.retain(|other, _| *other != tid);
+    true
+}
+
+/// Take the first stop yet to report, in thread creation order
+fn take_pending(threads: &mut IndexMap<Pid, Thread>) -> Option<WaitStatus> {
+    threads
+        .values_mut()
+        .find_map(|thread| thread.pending.take())
+}
+
+/// Threads with a trap yet to report
+fn trapped_threads(threads: &IndexMap<Pid, Thread>) -> Vec<Pid> {
+    threads
+        .iter()
+        .filter(|(_, thread)| {
+            matches!(
+                thread.pending,
+                Some(WaitStatus::Stopped(_, Signal::SIGTRAP))
+            )
+        })
+        .map(|(tid, _)| *tid)
+        .collect()
+}
+
+/// Faulting address of the signal a thread last stopped for
+fn fault_address(tid: Pid) -> Result<Address> {
+    let info = ptrace::getsiginfo(tid).map_err(|_| ErrorKind::ProcessEvent)?;
+    Ok(unsafe { info.si_addr() } as Address)
+}
+
+/// Send a signal to one thread of a process
+fn tgkill(pid: Pid, tid: Pid, signal: Signal) -> Result<()> {
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_tgkill,
+            pid.as_raw(),
+            tid.as_raw(),
+            signal as libc::c_int,
+        )
+    };
+    if ret < 0 {
+        Err(ErrorKind::ProcessEvent)?
+    }
+    Ok(())
+}
+
+/// Write a debug register of a thread
+fn poke_debug_register(tid: Pid, n: usize, value: u64) -> Result<()> {
+    let offset = DEBUGREG_OFFSET + n * 8;
+    let ret = unsafe {
+        libc::ptrace(
+            libc::PTRACE_POKEUSER,
+            tid.as_raw(),
+            offset as *mut libc::c_void,
+            value as *mut libc::c_void,
+        )
+    };
+    if ret < 0 {
+        Err(ErrorKind::Registers)?
+    }
+    Ok(())
+}
+
+/// Resume a thread until it reports the SIGSTOP on its way, passing on other
+/// signals. Returns false if it exited instead.
+fn swallow_sigstop(tid: Pid) -> Result<bool> {
+    let mut signal = None;
+    loop {
+        ptrace::cont(tid, signal).map_err(|_| ErrorKind::ProcessEvent)?;
+        match waitpid(tid, Some(WaitPidFlag::__WALL)) {
+            Ok(WaitStatus::Stopped(_, Signal::SIGSTOP)) => return Ok(true),
+            Ok(WaitStatus::Stopped(_, Signal::SIGTRAP)) => signal = None,
+            Ok(WaitStatus::Stopped(_, sig)) => signal = Some(sig),
+            Ok(WaitStatus::PtraceEvent(..)) => signal = None,
+            Ok(_) | Err(_) => return Ok(false),
+        }
+    }
+}
+
+/// Options to trace new threads, forked children and executed programs
+fn trace_options() -> ptrace::Options {
+    ptrace::Options::PTRACE_O_TRACECLONE
+        | ptrace::Options::PTRACE_O_TRACEFORK
+        | ptrace::Options::PTRACE_O_TRACEVFORK
+        | ptrace::Options::PTRACE_O_TRACEVFORKDONE
+        | ptrace::Options::PTRACE_O_TRACEEXEC
+}
+
+/// Write a byte to the memory of a stopped thread's process
+fn poke_byte(tid: Pid, addr: Address, byte: u8) -> Result<()> {
+    let addr = addr as ptrace::AddressType;
+    let word = ptrace::read(tid, addr)
+        .map_err(|_| ErrorKind::Read(addr as Address))?;
+    let mut bytes = word.to_le_bytes();
+    bytes[0] = byte;
+    let word = Address::from_le_bytes(bytes) as ptrace::AddressType;
+    Ok(ptrace::write(tid, addr, word)
+        .map_err(|_| ErrorKind::Write(addr as Address))?)
+}
+
+/// Detach a stopped thread, delivering its signal, or else a signal it
+/// stopped for that's yet to be reported, other than our own traps
+fn detach_thread(tid: Pid, mut thread: Thread) -> Result<()> {
+    let signal = match thread.pending.take() {
+        Some(WaitStatus::Stopped(_, Signal::SIGTRAP)) => None,
+        Some(WaitStatus::Stopped(_, signal)) => Some(signal),
+        _ => None,
+    };
+    let signal = thread.signal.take().or(signal);
+    // A SIGSTOP on its way would stop the process once detached
+    if thread.sigstop && !swallow_sigstop(tid)? {
+        return Ok(());
+    }
+    let signal = signal.map_or(0, |s| s as libc::c_int);
+    let ret = unsafe {
+        libc::ptrace(
+            libc::PTRACE_DETACH,
+            tid.as_raw(),
+            std::ptr::null_mut::<libc::c_void>(),
+            signal as *mut libc::c_void,
+        )
+    };
+    if ret < 0 {
+        Err(ErrorKind::ProcessEvent)?
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thread_numbers() {
+        let mut threads = IndexMap::new();
+        let mut next = 1;
+        for tid in 100..103 {
+            insert_thread(&mut threads, &mut next, Pid::from_raw(tid), true);
+        }
+        assert!(remove_thread(&mut threads, Pid::from_raw(101)));
+        assert!(!remove_thread(&mut threads, Pid::from_raw(101)));
+        insert_thread(&mut threads, &mut next, Pid::from_raw(103), false);
+        let nums: Vec<(i32, usize)> = threads
+            .iter()
+            .map(|(tid, thread)| (tid.as_raw(), thread.num))
+            .collect();
+        assert_eq!(nums, vec![(100, 1), (102, 3), (103, 4)]);
+        assert!(threads[&Pid::from_raw(103)].sigstop);
+    }
+
+    #[test]
+    fn test_take_pending() {
+        let mut threads = IndexMap::new();
+        let mut next = 1;
+        for tid in 100..103 {
+            insert_thread(&mut threads, &mut next, Pid::from_raw(tid), true);
+        }
+        let trap = WaitStatus::Stopped(Pid::from_raw(102), Signal::SIGTRAP);
+        let segv = WaitStatus::Stopped(Pid::from_raw(101), Signal::SIGSEGV);
+        threads[&Pid::from_raw(102)].pending = Some(trap);
+        threads[&Pid::from_raw(101)].pending = Some(segv);
+        assert_eq!(trapped_threads(&threads), vec![Pid::from_raw(102)]);
+        assert_eq!(take_pending(&mut threads), Some(segv));
+        assert_eq!(take_pending(&mut threads), Some(trap));
+        assert_eq!(take_pending(&mut threads), None);
+        assert!(trapped_threads(&threads).is_empty());
+    }
+
+    #[test]
+    fn test_fault_address() {
+        match fork().unwrap() {
+            ForkResult::Child => unsafe {
+                ptrace::traceme().unwrap();
+                std::ptr::read_volatile(0x10 as *const u8);
+                libc::_exit(0);
+            },
+            ForkResult::Parent { child } => {
+                let status = waitpid(child, None).unwrap();
+                assert_eq!(status, WaitStatus::Stopped(child, Signal::SIGSEGV));
+                assert_eq!(fault_address(child).unwrap(), 0x10);
+                signal::kill(child, Signal::SIGKILL).unwrap();
+                waitpid(child, None).unwrap();
+            }
+        }
     }
 }
@@ -0,0 +1,257 @@
+/// x86_64 general purpose registers, in gdb's display order
+pub const GENERAL: &[&str] = &[
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10",
+    "r11", "r12", "r13", "r14", "r15", "rip", "eflags", "cs", "ss", "ds", "es",
+    "fs", "gs", "fs_base", "gs_base",
+];
+
+/// Resolve gdb's architecture independent register aliases, e.g. "pc"
+pub fn alias(name: &str) -> &str {
+    match name {
+        "pc" => "rip",
+        "sp" => "rsp",
+        "fp" => "rbp",
+        name => name,
+    }
+}
+
+/// EFLAGS bits, from least significant
+const EFLAGS: &[(u32, &str)] = &[
+    (0, "CF"),
+    (2, "PF"),
+    (4, "AF"),
+    (6, "ZF"),
+    (7, "SF"),
+    (8, "TF"),
+    (9, "IF"),
+    (10, "DF"),
+    (11, "OF"),
+    (14, "NT"),
+    (16, "RF"),
+    (17, "VM"),
+    (18, "AC"),
+    (19, "VIF"),
+    (20, "VIP"),
+    (21, "ID"),
+];
+
+/// MXCSR bits, from least significant
+const MXCSR: &[(u32, &str)] = &[
+    (0, "IE"),
+    (1, "DE"),
+    (2, "ZE"),
+    (3, "OE"),
+    (4, "UE"),
+    (5, "PE"),
+    (6, "DAZ"),
+    (7, "IM"),
+    (8, "DM"),
+    (9, "ZM"),
+    (10, "OM"),
+    (11, "UM"),
+    (12, "PM"),
+    (15, "FZ"),
+];
+
+/// General purpose register state of a stopped thread
+#[derive(Clone, Copy)]
+pub struct Registers {
+    pub(super) regs: libc::user_regs_struct,
+}
+
+impl std::fmt::Debug for Registers {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_map()
+            .entries(GENERAL.iter().map(|name| (name, self.get(name))))
+            .finish()
+    }
+}
+
+impl Registers {
+    /// Value of a register by name, e.g. "rax", "eflags" or "fs_base"
+    pub fn get(&self, name: &str) -> Option<u64> {
+        // Registers are Copy, look up the field on a scratch copy
+        let mut regs = *self;
+        regs.field(name).map(|value| *value)
+    }
+
+    /// Set a register by name, returning false for an unknown name
+    pub fn set(&mut self, name: &str, value: u64) -> bool {
+        match self.field(name) {
+            Some(field) => {
+                *field = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn field(&mut self, name: &str) -> Option<&mut u64> {
+        let r = &mut self.regs;
+        let field = match name {
+            "rax" => &mut r.rax,
+            "rbx" => &mut r.rbx,
+            "rcx" => &mut r.rcx,
+            "rdx" => &mut r.rdx,
+            "rsi" => &mut r.rsi,
+            "rdi" => &mut r.rdi,
+            "rbp" => &mut r.rbp,
+            "rsp" => &mut r.rsp,
+            "r8" => &mut r.r8,
+            "r9" => &mut r.r9,
+            "r10" => &mut r.r10,
+            "r11" => &mut r.r11,
+            "r12" => &mut r.r12,
+            "r13" => &mut r.r13,
+            "r14" => &mut r.r14,
+            "r15" => &mut r.r15,
+            "rip" => &mut r.rip,
+            "eflags" => &mut r.eflags,
+            "cs" => &mut r.cs,
+            "ss" => &mut r.ss,
+            "ds" => &mut r.ds,
+            "es" => &mut r.es,
+            "fs" => &mut r.fs,
+            "gs" => &mut r.gs,
+            "fs_base" => &mut r.fs_base,
+            "gs_base" => &mut r.gs_base,
+            _ => return None,
+        };
+        Some(field)
+    }
+
+    /// Registers and their values, in gdb's display order
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, u64)> + '_ {
+        GENERAL
+            .iter()
+            .map(move |&name| (name, self.get(name).unwrap()))
+    }
+}
+
+/// Floating point, vector and AVX register state of a stopped thread
+#[derive(Clone, Copy)]
+pub struct FpRegisters {
+    pub(super) regs: libc::user_fpregs_struct,
+    /// Upper halves of ymm0-15, if the processor has AVX
+    pub(super) ymmh: Option<[u128; 16]>,
+}
+
+impl std::fmt::Debug for FpRegisters {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("FpRegisters")
+            .field("fctrl", &self.regs.cwd)
+            .field("fstat", &self.regs.swd)
+            .field("mxcsr", &self.regs.mxcsr)
+            .finish()
+    }
+}
+
+impl FpRegisters {
+    /// x87 control registers, as named by gdb
+    pub fn control(&self) -> Vec<(&'static str, u64)> {
+        let r = &self.regs;
+        vec![
+            ("fctrl", r.cwd.into()),
+            ("fstat", r.swd.into()),
+            ("ftag", r.ftw.into()),
+            ("fiseg", 0),
+            ("fioff", r.rip & 0xffff_ffff),
+            ("foseg", 0),
+            ("fooff", r.rdp & 0xffff_ffff),
+            ("fop", r.fop.into()),
+        ]
+    }
+
+    /// Raw 80-bit x87 stack register, st0-7
+    pub fn st(&self, n: usize) -> [u8; 10] {
+        let mut raw = [0; 10];
+        for (i, word) in self.regs.st_space[n * 4..n * 4 + 3].iter().enumerate()
+        {
+            let bytes = word.to_le_bytes();
+            let len = std::cmp::min(4, 10 - i * 4);
+            raw[i * 4..i * 4 + len].copy_from_slice(&bytes[..len]);
+        }
+        raw
+    }
+
+    /// SSE register, xmm0-15
+    pub fn xmm(&self, n: usize) -> u128 {
+        self.regs.xmm_space[n * 4..n * 4 + 4]
+            .iter()
+            .rev()
+            .fold(0, |value, &word| value << 32 | u128::from(word))
+    }
+
+    /// AVX register, ymm0-15, as low and high halves
+    pub fn ymm(&self, n: usize) -> Option<(u128, u128)> {
+        self.ymmh.map(|ymmh| (self.xmm(n), ymmh[n]))
+    }
+
+    /// SSE control and status register
+    pub fn mxcsr(&self) -> u32 {
+        self.regs.mxcsr
+    }
+}
+
+/// Names of the EFLAGS bits set in a value
+pub fn eflags_names(value: u64) -> Vec<&'static str> {
+    flag_names(EFLAGS, value)
+}
+
+/// Names of the MXCSR bits set in a value
+pub fn mxcsr_names(value: u32) -> Vec<&'static str> {
+    flag_names(MXCSR, value.into())
+}
+
+fn flag_names(flags: &[(u32, &'static str)], value: u64) -> Vec<&'static str> {
+    flags
+        .iter()
+        .filter(|(bit, _)| value & 1 << bit != 0)
+        .map(|&(_, name)| name)
+        .collect()
+}
+
+/// Convert an 80-bit x87 extended precision value to the nearest f64
+pub fn extended_to_f64(raw: [u8; 10]) -> f64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&raw[..8]);
+    let mantissa = u64::from_le_bytes(bytes);
+    let exp = i32::from(u16::from_le_bytes([raw[8], raw[9]]) & 0x7fff);
+    let sign = match raw[9] & 0x80 {
+        0 => 1.0,
+        _ => -1.0,
+    };
+    let value = match exp {
+        0 if mantissa == 0 => 0.0,
+        0x7fff if mantissa << 1 == 0 => f64::INFINITY,
+        0x7fff => f64::NAN,
+        // Denormals share the exponent of the smallest normal
+        0 => mantissa as f64 * 2f64.powi(1 - 16383 - 63),
+        exp => mantissa as f64 * 2f64.powi(exp - 16383 - 63),
+    };
+    sign * value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag_names() {
+        assert_eq!(eflags_names(0x246), vec!["PF", "ZF", "IF"]);
+        assert_eq!(
+            mxcsr_names(0x1f80),
+            vec!["IM", "DM", "ZM", "OM", "UM", "PM"]
+        );
+    }
+
+    #[test]
+    fn test_extended_to_f64() {
+        // 1.0: exponent bias 16383, explicit integer bit
+        let one = [0, 0, 0, 0, 0, 0, 0, 0x80, 0xff, 0x3f];
+        assert_eq!(extended_to_f64(one), 1.0);
+        let minus_three = [0, 0, 0, 0, 0, 0, 0, 0xc0, 0x00, 0xc0];
+        assert_eq!(extended_to_f64(minus_three), -3.0);
+        assert_eq!(extended_to_f64([0; 10]), 0.0);
+    }
+}
@@ -32,10 +32,17 @@ fn command_prompt(opt: Opt) -> Result<()> {
         }
     }
     shortstop.handle_command(Cmd::Set {
-        expr: None,
+        expr: Vec::new(),
         cmd: Some(Set::Args { args: opt.args }),
     })?;
 
+    // Attach to a running process
+    if let Some(pid) = opt.pid {
+        if let Err(e) = shortstop.handle_command(Cmd::Attach { pid }) {
+            println!("{}", e);
+        }
+    }
+
     let mut rl = Editor::<()>::new();
     loop {
         let readline = rl.readline("(dbg) ");
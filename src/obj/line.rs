@@ -0,0 +1,529 @@
+use std::path::{Path, PathBuf};
+
+use failure::{bail, format_err};
+
+use super::Result;
+
+// Standard opcodes
+const DW_LNS_COPY: u8 = 1;
+const DW_LNS_ADVANCE_PC: u8 = 2;
+const DW_LNS_ADVANCE_LINE: u8 = 3;
+const DW_LNS_SET_FILE: u8 = 4;
+const DW_LNS_NEGATE_STMT: u8 = 6;
+const DW_LNS_CONST_ADD_PC: u8 = 8;
+const DW_LNS_FIXED_ADVANCE_PC: u8 = 9;
+
+// Extended opcodes
+const DW_LNE_END_SEQUENCE: u8 = 1;
+const DW_LNE_SET_ADDRESS: u8 = 2;
+const DW_LNE_DEFINE_FILE: u8 = 3;
+
+// DWARF 5 entry format content types
+const DW_LNCT_PATH: u64 = 1;
+const DW_LNCT_DIRECTORY_INDEX: u64 = 2;
+
+// DWARF 5 entry format attribute forms
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_DATA1: u64 = 0x0b;
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_DATA16: u64 = 0x1e;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_STRP: u64 = 0x0e;
+const DW_FORM_UDATA: u64 = 0x0f;
+const DW_FORM_LINE_STRP: u64 = 0x1f;
+
+/// Line number table row
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub addr: usize,
+    /// Index into the table's file list
+    pub file: usize,
+    pub line: usize,
+    pub is_stmt: bool,
+    pub end_sequence: bool,
+}
+
+/// Address to source line mapping decoded from .debug_line
+#[derive(Debug, Default)]
+pub struct LineTable {
+    files: Vec<PathBuf>,
+    rows: Vec<Row>,
+}
+
+/// Debug string sections referenced by DWARF 5 line headers
+#[derive(Default)]
+pub struct Strings<'a> {
+    pub debug_str: &'a [u8],
+    pub debug_line_str: &'a [u8],
+}
+
+impl LineTable {
+    /// Decode every line number program in a .debug_line section
+    pub fn parse(data: &[u8], strings: &Strings) -> Result<Self> {
+        let mut table = LineTable::default();
+        let mut reader = Reader::new(data);
+        while !reader.is_empty() {
+            table.parse_unit(&mut reader, strings)?;
+        }
+        Ok(table)
+    }
+
+    /// Shift every row's address, wrapping to move down
+    pub fn relocate(&mut self, delta: usize) {
+        for row in self.rows.iter_mut() {
+            row.addr = row.addr.wrapping_add(delta);
+        }
+    }
+
+    /// File path of a row
+    pub fn file(&self, row: &Row) -> &Path {
+        &self.files[row.file]
+    }
+
+    /// Find the source line containing an address
+    pub fn find(&self, addr: usize) -> Option<&Row> {
+        let mut found = None;
+        for pair in self.rows.windows(2) {
+            let (row, next) = (&pair[0], &pair[1]);
+            if !row.end_sequence && row.addr <= addr && addr < next.addr {
+                found = Some(row);
+            }
+        }
+        found
+    }
+
+    /// Check for a source file in the table
+    pub fn has_file(&self, file: &str) -> bool {
+        self.files.iter().any(|path| path.ends_with(file))
+    }
+
+    /// Find the lowest address for a source line. If the line has no code,
+    /// the next line that does is used instead, as gdb does.
+    pub fn line_address(&self, file: &str, line: usize) -> Option<&Row> {
+        let rows = self.rows.iter().filter(|row| {
+            !row.end_sequence
+                && row.is_stmt
+                && row.line >= line
+                && self.files[row.file].ends_with(file)
+        });
+        rows.min_by_key(|row| (row.line, row.addr))
+    }
+
+    /// Address after a function's prologue, i.e. the first address of the
+    /// function's second line
+    pub fn skip_prologue(&self, start: usize, end: usize) -> Option<usize> {
+        let mut rows = self
+            .rows
+            .iter()
+            .filter(|row| !row.end_sequence && start <= row.addr)
+            .filter(|row| row.addr < end);
+        let first = rows.next()?;
+        rows.find(|row| row.line != first.line && row.addr > first.addr)
+            .map(|row| row.addr)
+    }
+
+    /// Decode one unit's header and line number program
+    fn parse_unit(
+        &mut self,
+        reader: &mut Reader,
+        strings: &Strings,
+    ) -> Result<()> {
+        let mut length = reader.u32()? as u64;
+        let mut offset_size = 4;
+        if length == 0xffff_ffff {
+            length = reader.u64()?;
+            offset_size = 8;
+        }
+        let mut unit = Reader::new(reader.take(length as usize)?);
+
+        let version = unit.u16()?;
+        if !(2..=5).contains(&version) {
+            bail!("unsupported DWARF line table version {}", version);
+        }
+        if version >= 5 {
+            let _address_size = unit.u8()?;
+            let _segment_selector_size = unit.u8()?;
+        }
+        let header_length = unit.offset(offset_size)?;
+        let mut program = unit.clone();
+        program.skip(header_length as usize)?;
+
+        let min_inst_length = unit.u8()? as usize;
+        if version >= 4 {
+            let _max_ops_per_inst = unit.u8()?;
+        }
+        let default_is_stmt = unit.u8()? != 0;
+        let line_base = unit.u8()? as i8 as i64;
+        let line_range = unit.u8()?;
+        if line_range == 0 {
+            bail!("invalid DWARF line range 0");
+        }
+        let opcode_base = unit.u8()?;
+        let opcode_lengths =
+            unit.take(opcode_base.saturating_sub(1) as usize)?;
+
+        // File indices in the program are relative to this unit
+        let file_base = self.files.len();
+        let file_index = |idx: u64| file_base.wrapping_add(idx as usize);
+
+        if version >= 5 {
+            let dirs = unit.entries(offset_size, strings)?;
+            let dirs: Vec<PathBuf> = dirs.into_iter().map(|d| d.0).collect();
+            for (name, dir) in unit.entries(offset_size, strings)? {
+                let dir = dirs.get(dir as usize);
+                self.files.push(join_path(dir, name));
+            }
+        } else {
+            let mut dirs = vec![PathBuf::new()];
+            loop {
+                let dir = unit.cstr()?;
+                if dir.is_empty() {
+                    break;
+                }
+                dirs.push(PathBuf::from(dir));
+            }
+            // DWARF < 5 file numbers start at 1
+            self.files.push(PathBuf::new());
+            loop {
+                let name = unit.cstr()?;
+                if name.is_empty() {
+                    break;
+                }
+                let dir = unit.uleb()?;
+                let _mtime = unit.uleb()?;
+                let _length = unit.uleb()?;
+                self.files
+                    .push(join_path(dirs.get(dir as usize), name.into()));
+            }
+        }
+
+        // Line number program state machine
+        let reset = Row {
+            addr: 0,
+            file: file_index(1),
+            line: 1,
+            is_stmt: default_is_stmt,
+            end_sequence: false,
+        };
+        // Addresses and lines wrap rather than overflow on malformed programs
+        let advance = |addr: usize, ops: usize| {
+            addr.wrapping_add(ops.wrapping_mul(min_inst_length))
+        };
+        let mut state = reset.clone();
+        while !program.is_empty() {
+            let opcode = program.u8()?;
+            if opcode >= opcode_base {
+                let adjusted = opcode - opcode_base;
+                state.addr =
+                    advance(state.addr, (adjusted / line_range) as usize);
+                state.line = (state.line as i64)
+                    .wrapping_add(line_base + (adjusted % line_range) as i64)
+                    as usize;
+                self.rows.push(state.clone());
+                continue;
+            }
+            match opcode {
+                0 => {
+                    let len = program.uleb()? as usize;
+                    let mut ext = Reader::new(program.take(len)?);
+                    match ext.u8()? {
+                        DW_LNE_END_SEQUENCE => {
+                            state.end_sequence = true;
+                            self.rows.push(state);
+                            state = reset.clone();
+                        }
+                        DW_LNE_SET_ADDRESS => {
+                            state.addr = ext.offset(len - 1)? as usize;
+                        }
+                        DW_LNE_DEFINE_FILE => {
+                            let name = ext.cstr()?;
+                            self.files.push(PathBuf::from(name));
+                        }
+                        _ => {}
+                    }
+                }
+                DW_LNS_COPY => self.rows.push(state.clone()),
+                DW_LNS_ADVANCE_PC => {
+                    state.addr = advance(state.addr, program.uleb()? as usize);
+                }
+                DW_LNS_ADVANCE_LINE => {
+                    state.line = (state.line as i64)
+                        .wrapping_add(program.sleb()?)
+                        as usize;
+                }
+                DW_LNS_SET_FILE => state.file = file_index(program.uleb()?),
+                DW_LNS_NEGATE_STMT => state.is_stmt = !state.is_stmt,
+                DW_LNS_CONST_ADD_PC => {
+                    let adjusted = 255 - opcode_base;
+                    state.addr =
+                        advance(state.addr, (adjusted / line_range) as usize);
+                }
+                DW_LNS_FIXED_ADVANCE_PC => {
+                    state.addr =
+                        state.addr.wrapping_add(program.u16()? as usize);
+                }
+                _ => {
+                    // Skip operands of standard opcodes we don't track
+                    for _ in 0..opcode_lengths[opcode as usize - 1] {
+                        program.uleb()?;
+                    }
+                }
+            }
+        }
+
+        // Clamp indices from malformed programs
+        let count = self.files.len();
+        for row in self.rows.iter_mut() {
+            if row.file >= count {
+                row.file = file_base;
+            }
+        }
+        if self.files.len() == file_base {
+            self.files.push(PathBuf::new());
+        }
+        Ok(())
+    }
+}
+
+/// Hand-built tables for tests elsewhere
+#[cfg(test)]
+impl LineTable {
+    /// One sequence of (address, line) rows in a file, ending at an address
+    pub fn sequence(file: &str, rows: &[(usize, usize)], end: usize) -> Self {
+        let row = |addr, line, end_sequence| Row {
+            addr,
+            file: 0,
+            line,
+            is_stmt: true,
+            end_sequence,
+        };
+        let mut rows: Vec<Row> = rows
+            .iter()
+            .map(|&(addr, line)| row(addr, line, false))
+            .collect();
+        let last = rows.last().map_or(1, |row| row.line);
+        rows.push(row(end, last, true));
+        LineTable {
+            files: vec![PathBuf::from(file)],
+            rows,
+        }
+    }
+}
+
+fn join_path(dir: Option<&PathBuf>, name: PathBuf) -> PathBuf {
+    match dir {
+        Some(dir) => dir.join(name),
+        None => name,
+    }
+}
+
+/// Little-endian cursor over DWARF section data
+#[derive(Clone)]
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if n > self.data.len() {
+            bail!("unexpected end of DWARF data");
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn skip(&mut self, n: usize) -> Result<()> {
+        self.take(n).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(self.offset(2)? as u16)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(self.offset(4)? as u32)
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        self.offset(8)
+    }
+
+    /// Unsigned value of up to 8 bytes
+    fn offset(&mut self, size: usize) -> Result<u64> {
+        if size > 8 {
+            bail!("unsupported DWARF value size {}", size);
+        }
+        let mut bytes = [0; 8];
+        bytes[..size].copy_from_slice(self.take(size)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn uleb(&mut self) -> Result<u64> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as u64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break Ok(value);
+            }
+        }
+    }
+
+    fn sleb(&mut self) -> Result<i64> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                break Ok(value);
+            }
+        }
+    }
+
+    fn cstr(&mut self) -> Result<String> {
+        let len = self
+            .data
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| format_err!("unterminated DWARF string"))?;
+        let s = String::from_utf8_lossy(self.take(len)?).into_owned();
+        self.skip(1)?;
+        Ok(s)
+    }
+
+    /// String at an offset in another section
+    fn strp(&mut self, size: usize, section: &[u8]) -> Result<String> {
+        let offset = self.offset(size)? as usize;
+        let mut reader = Reader::new(section.get(offset..).unwrap_or(&[]));
+        reader.cstr()
+    }
+
+    /// DWARF 5 directory or file name table, as (path, directory index)
+    fn entries(
+        &mut self,
+        offset_size: usize,
+        strings: &Strings,
+    ) -> Result<Vec<(PathBuf, u64)>> {
+        let mut formats = Vec::new();
+        for _ in 0..self.u8()? {
+            formats.push((self.uleb()?, self.uleb()?));
+        }
+
+        let mut entries = Vec::new();
+        for _ in 0..self.uleb()? {
+            let mut path = String::new();
+            let mut dir = 0;
+            for (content, form) in formats.iter() {
+                let mut text = None;
+                let mut value = 0;
+                match *form {
+                    DW_FORM_STRING => text = Some(self.cstr()?),
+                    DW_FORM_LINE_STRP => {
+                        let section = strings.debug_line_str;
+                        text = Some(self.strp(offset_size, section)?);
+                    }
+                    DW_FORM_STRP => {
+                        let section = strings.debug_str;
+                        text = Some(self.strp(offset_size, section)?);
+                    }
+                    DW_FORM_UDATA => value = self.uleb()?,
+                    DW_FORM_DATA1 => value = self.offset(1)?,
+                    DW_FORM_DATA2 => value = self.offset(2)?,
+                    DW_FORM_DATA4 => value = self.offset(4)?,
+                    DW_FORM_DATA8 => value = self.offset(8)?,
+                    DW_FORM_DATA16 => self.skip(16)?,
+                    DW_FORM_BLOCK => {
+                        let len = self.uleb()? as usize;
+                        self.skip(len)?;
+                    }
+                    form => bail!("unsupported DWARF form 0x{:x}", form),
+                }
+                // Timestamps, sizes and checksums are not needed
+                match *content {
+                    DW_LNCT_PATH => path = text.unwrap_or_default(),
+                    DW_LNCT_DIRECTORY_INDEX => dir = value,
+                    _ => {}
+                }
+            }
+            entries.push((PathBuf::from(path), dir));
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// DWARF 2 unit for file a.c with a line number program
+    fn unit(program: &[u8]) -> Vec<u8> {
+        let mut header = vec![1, 1, -5i8 as u8, 14, 13];
+        header.extend_from_slice(&[0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+        header.extend_from_slice(b"\0a.c\0\0\0\0\0");
+        let mut unit = 2u16.to_le_bytes().to_vec();
+        unit.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        unit.extend(header);
+        unit.extend_from_slice(program);
+        let mut data = (unit.len() as u32).to_le_bytes().to_vec();
+        data.extend(unit);
+        data
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut program = vec![0, 9, DW_LNE_SET_ADDRESS];
+        program.extend_from_slice(&0x401000u64.to_le_bytes());
+        // Line 3 at 0x401000, line 4 at 0x401004, ending at 0x401008
+        program.extend_from_slice(&[DW_LNS_ADVANCE_LINE, 2, DW_LNS_COPY]);
+        program.extend_from_slice(&[13 + 5 + 4 * 14 + 1]);
+        program.extend_from_slice(&[DW_LNS_ADVANCE_PC, 4]);
+        program.extend_from_slice(&[0, 1, DW_LNE_END_SEQUENCE]);
+        let table = LineTable::parse(&unit(&program), &Default::default());
+        let table = table.unwrap();
+        let row = table.find(0x401006).unwrap();
+        assert_eq!((row.addr, row.line), (0x401004, 4));
+        assert_eq!(table.file(row), Path::new("a.c"));
+        assert_eq!(table.skip_prologue(0x401000, 0x401008), Some(0x401004));
+    }
+
+    #[test]
+    fn test_parse_overflow() {
+        let mut program = vec![0, 9, DW_LNE_SET_ADDRESS];
+        program.extend_from_slice(&[0xff; 8]);
+        program.extend_from_slice(&[DW_LNS_ADVANCE_PC, 0xff, 0xff, 0x7f]);
+        program.extend_from_slice(&[DW_LNS_CONST_ADD_PC, 0xff]);
+        program.extend_from_slice(&[DW_LNS_FIXED_ADVANCE_PC, 0xff, 0xff]);
+        program.push(DW_LNS_ADVANCE_LINE);
+        program.extend_from_slice(&[0xff; 9]);
+        program.extend_from_slice(&[0x00, DW_LNS_COPY, DW_LNS_SET_FILE]);
+        program.extend_from_slice(&[0xff; 9]);
+        program.extend_from_slice(&[0x01, DW_LNS_COPY]);
+        assert!(LineTable::parse(&unit(&program), &Default::default()).is_ok());
+    }
+}
@@ -1,16 +1,428 @@
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 
-use failure::Error;
+use failure::{bail, Error, ResultExt};
+use goblin::elf::{
+    header::ET_DYN,
+    program_header::PT_LOAD,
+    section_header::{SHF_ALLOC, SHT_NOBITS},
+    sym::{STT_FUNC, STT_GNU_IFUNC, STT_NOTYPE, STT_OBJECT},
+    Elf,
+};
+
+mod line;
+pub use line::LineTable;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Symbol type, reduced to what the debugger cares about
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Function,
+    Object,
+    Other,
+}
+
+/// Named address from the symbol tables
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: usize,
+    pub size: usize,
+    pub kind: SymbolKind,
+}
+
+/// Section header summary
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub name: String,
+    pub addr: usize,
+    pub offset: usize,
+    pub size: usize,
+    pub alloc: bool,
+    pub nobits: bool,
+}
+
+/// Loadable program segment
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub vaddr: usize,
+    pub offset: usize,
+    pub filesz: usize,
+    pub memsz: usize,
+}
+
 #[derive(Debug)]
 pub struct Binary {
     pub path: PathBuf,
+    /// Entry point virtual address
+    pub entry: usize,
+    /// 64-bit ELF class
+    pub is_64: bool,
+    /// Position independent executable (ET_DYN)
+    pub is_pie: bool,
+    /// Load bias added to every address, zero until relocated
+    pub bias: usize,
+    /// Function and object symbols from .symtab and .dynsym, sorted by address
+    pub symbols: Vec<Symbol>,
+    pub sections: Vec<Section>,
+    pub segments: Vec<Segment>,
+    /// Source line table from .debug_line, empty without debug info
+    pub lines: LineTable,
+    // Raw file image
+    data: Vec<u8>,
 }
 
 impl Binary {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
         let path = path.as_ref().canonicalize()?;
-        Ok(Binary { path })
+        let data = std::fs::read(&path)?;
+        let elf = match Elf::parse(&data) {
+            Ok(elf) => elf,
+            Err(_) => bail!(
+                "\"{}\": not in executable format: file format not recognized",
+                path.display()
+            ),
+        };
+
+        let symbols = Self::load_symbols(&elf);
+        let sections = Self::load_sections(&elf)
+            .with_context(|_| format!("{}", path.display()))?;
+        let segments = elf
+            .program_headers
+            .iter()
+            .filter(|ph| ph.p_type == PT_LOAD)
+            .map(|ph| Segment {
+                vaddr: ph.p_vaddr as usize,
+                offset: ph.p_offset as usize,
+                filesz: ph.p_filesz as usize,
+                memsz: ph.p_memsz as usize,
+            })
+            .collect();
+
+        let mut bin = Binary {
+            entry: elf.entry as usize,
+            is_64: elf.is_64,
+            is_pie: elf.header.e_type == ET_DYN,
+            bias: 0,
+            symbols,
+            sections,
+            segments,
+            lines: Default::default(),
+            path,
+            data,
+        };
+
+        // Missing or unsupported debug info only loses source lines
+        if let Ok(lines) = bin.load_lines() {
+            bin.lines = lines;
+        }
+        Ok(bin)
+    }
+
+    /// Collect defined symbols from both symbol tables
+    fn load_symbols(elf: &Elf) -> Vec<Symbol> {
+        let symtab = elf.syms.iter().map(|sym| (sym, &elf.strtab));
+        let dynsym = elf.dynsyms.iter().map(|sym| (sym, &elf.dynstrtab));
+
+        let mut symbols: Vec<Symbol> = symtab
+            .chain(dynsym)
+            .filter(|(sym, _)| sym.st_shndx != 0 && sym.st_value != 0)
+            .filter_map(|(sym, strtab)| {
+                let kind = match sym.st_type() {
+                    STT_FUNC | STT_GNU_IFUNC => SymbolKind::Function,
+                    STT_OBJECT => SymbolKind::Object,
+                    STT_NOTYPE => SymbolKind::Other,
+                    _ => return None,
+                };
+                let name = strtab.get_unsafe(sym.st_name)?;
+                if name.is_empty() {
+                    return None;
+                }
+                Some(Symbol {
+                    name: name.to_owned(),
+                    addr: sym.st_value as usize,
+                    size: sym.st_size as usize,
+                    kind,
+                })
+            })
+            .collect();
+        symbols.extend(Self::plt_symbols(elf));
+
+        // The dynamic symbol table mostly duplicates .symtab
+        symbols.sort_by(|a, b| a.addr.cmp(&b.addr).then(a.name.cmp(&b.name)));
+        symbols.dedup_by(|a, b| a.addr == b.addr && a.name == b.name);
+        symbols
+    }
+
+    /// Synthesize "name@plt" symbols for PLT stubs. Each stub is 16 bytes,
+    /// in .plt.sec when present, otherwise after the PLT0 stub in .plt.
+    fn plt_symbols(elf: &Elf) -> Vec<Symbol> {
+        let section = |name| {
+            elf.section_headers
+                .iter()
+                .find(|sh| elf.shdr_strtab.get_unsafe(sh.sh_name) == Some(name))
+        };
+        let start = match (section(".plt.sec"), section(".plt")) {
+            (Some(sh), _) => sh.sh_addr as usize,
+            (None, Some(sh)) => sh.sh_addr as usize + 16,
+            (None, None) => return Vec::new(),
+        };
+        elf.pltrelocs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, reloc)| {
+                let sym = elf.dynsyms.get(reloc.r_sym)?;
+                let name = elf.dynstrtab.get_unsafe(sym.st_name)?;
+                Some(Symbol {
+                    name: format!("{}@plt", name),
+                    addr: start + i * 16,
+                    size: 16,
+                    kind: SymbolKind::Function,
+                })
+            })
+            .collect()
+    }
+
+    fn load_sections(elf: &Elf) -> Result<Vec<Section>> {
+        let mut sections = Vec::new();
+        for sh in elf.section_headers.iter().skip(1) {
+            let name = match elf.shdr_strtab.get(sh.sh_name) {
+                Some(name) => name?.to_owned(),
+                None => String::new(),
+            };
+            sections.push(Section {
+                name,
+                addr: sh.sh_addr as usize,
+                offset: sh.sh_offset as usize,
+                size: sh.sh_size as usize,
+                alloc: sh.sh_flags as u32 & SHF_ALLOC != 0,
+                nobits: sh.sh_type == SHT_NOBITS,
+            });
+        }
+        Ok(sections)
+    }
+
+    fn load_lines(&self) -> Result<LineTable> {
+        let data = |name| {
+            self.section(name)
+                .and_then(|sec| self.section_data(sec))
+                .unwrap_or(&[])
+        };
+        let strings = line::Strings {
+            debug_str: data(".debug_str"),
+            debug_line_str: data(".debug_line_str"),
+        };
+        LineTable::parse(data(".debug_line"), &strings)
+    }
+
+    /// Relocate every address to a new load bias, e.g. where a position
+    /// independent executable was loaded
+    pub fn relocate(&mut self, bias: usize) {
+        let delta = bias.wrapping_sub(self.bias);
+        let relocate = |addr: &mut usize| *addr = addr.wrapping_add(delta);
+        relocate(&mut self.entry);
+        self.symbols
+            .iter_mut()
+            .for_each(|sym| relocate(&mut sym.addr));
+        self.sections
+            .iter_mut()
+            .filter(|sec| sec.alloc)
+            .for_each(|sec| relocate(&mut sec.addr));
+        self.segments
+            .iter_mut()
+            .for_each(|seg| relocate(&mut seg.vaddr));
+        self.lines.relocate(delta);
+        self.bias = bias;
+    }
+
+    /// Find a symbol by name, preferring functions
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        let mut matches = self.symbols.iter().filter(|sym| sym.name == name);
+        let first = matches.next()?;
+        match first.kind {
+            SymbolKind::Function => Some(first),
+            _ => matches
+                .find(|sym| sym.kind == SymbolKind::Function)
+                .or(Some(first)),
+        }
+    }
+
+    /// Find the symbol containing an address, and the offset into it.
+    /// Unsized symbols (e.g. assembly labels) cover addresses up to the next
+    /// symbol in the same section.
+    pub fn symbolize(&self, addr: usize) -> Option<(&Symbol, usize)> {
+        let mut nearest = None;
+        let mut passed_sized = false;
+        for sym in self.symbols.iter().rev().skip_while(|sym| sym.addr > addr) {
+            if addr < sym.addr + sym.size {
+                return Some((sym, addr - sym.addr));
+            }
+            // A label before a sized symbol ends where that symbol starts
+            match sym.size {
+                0 if nearest.is_none() && !passed_sized => nearest = Some(sym),
+                0 => (),
+                _ => passed_sized = true,
+            }
+        }
+        let sym = nearest?;
+        let section = self.section_at(addr)?;
+        if section.addr <= sym.addr {
+            Some((sym, addr - sym.addr))
+        } else {
+            None
+        }
+    }
+
+    /// Find the function containing an address and its address range.
+    /// Unsized functions extend to the next symbol or end of their section.
+    pub fn function_range(
+        &self,
+        addr: usize,
+    ) -> Option<(&Symbol, Range<usize>)> {
+        let (sym, _) = self.symbolize(addr)?;
+        let end = match sym.size {
+            0 => {
+                let next = self.symbols.iter().find(|s| s.addr > sym.addr);
+                let section = self.section_at(sym.addr)?;
+                let section_end = section.addr + section.size;
+                next.map_or(section_end, |s| s.addr.min(section_end))
+            }
+            size => sym.addr + size,
+        };
+        Some((sym, sym.addr..end))
+    }
+
+    /// Find a section by name
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|sec| sec.name == name)
+    }
+
+    /// Find the allocated section containing an address
+    pub fn section_at(&self, addr: usize) -> Option<&Section> {
+        self.sections
+            .iter()
+            .filter(|sec| sec.alloc)
+            .find(|sec| sec.addr <= addr && addr < sec.addr + sec.size)
+    }
+
+    /// Raw file contents of a section
+    pub fn section_data(&self, sec: &Section) -> Option<&[u8]> {
+        match sec.nobits {
+            true => None,
+            false => self.data.get(sec.offset..sec.offset + sec.size),
+        }
+    }
+
+    /// Source file and line containing an address
+    pub fn source_line(&self, addr: usize) -> Option<(&Path, usize)> {
+        let row = self.lines.find(addr)?;
+        Some((self.lines.file(row), row.line))
+    }
+
+    /// Read memory from the file image by virtual address, as it would be
+    /// loaded. Bytes past a segment's file size (e.g. .bss) read as zero.
+    pub fn read(&self, addr: usize, size: usize) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(size);
+        while data.len() < size {
+            let vaddr = addr + data.len();
+            let seg = match self.segment_at(vaddr) {
+                Some(seg) => seg,
+                None => bail!("Cannot access memory at address {:#x}", vaddr),
+            };
+            let end = (seg.vaddr + seg.memsz).min(addr + size);
+            let file_end = (seg.vaddr + seg.filesz).min(end);
+            if vaddr < file_end {
+                let offset = seg.offset + (vaddr - seg.vaddr);
+                match self.data.get(offset..offset + (file_end - vaddr)) {
+                    Some(bytes) => data.extend_from_slice(bytes),
+                    None => {
+                        bail!("Cannot access memory at address {:#x}", vaddr)
+                    }
+                }
+            }
+            data.resize(end - addr, 0);
+        }
+        Ok(data)
+    }
+
+    /// Find the loadable segment containing an address
+    pub fn segment_at(&self, addr: usize) -> Option<&Segment> {
+        self.segments
+            .iter()
+            .find(|seg| seg.vaddr <= addr && addr < seg.vaddr + seg.memsz)
+    }
+
+    /// Virtual address range spanned by all loadable segments
+    pub fn address_range(&self) -> Range<usize> {
+        let start = self.segments.iter().map(|seg| seg.vaddr).min();
+        let end = self.segments.iter().map(|seg| seg.vaddr + seg.memsz).max();
+        start.unwrap_or(0)..end.unwrap_or(0)
+    }
+}
+
+/// Hand-built binaries for tests elsewhere
+#[cfg(test)]
+impl Binary {
+    /// Binary of symbols in a .text section, with a line table but no file
+    /// contents
+    pub fn fixture(
+        text: Range<usize>,
+        symbols: Vec<Symbol>,
+        lines: LineTable,
+    ) -> Self {
+        Binary {
+            path: PathBuf::new(),
+            entry: text.start,
+            is_64: true,
+            is_pie: false,
+            bias: 0,
+            symbols,
+            sections: vec![Section {
+                name: ".text".to_owned(),
+                addr: text.start,
+                offset: text.start,
+                size: text.end - text.start,
+                alloc: true,
+                nobits: false,
+            }],
+            segments: Vec::new(),
+            lines,
+            data: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(name: &str, addr: usize, size: usize) -> Symbol {
+        Symbol {
+            name: name.to_owned(),
+            addr,
+            size,
+            kind: SymbolKind::Function,
+        }
+    }
+
+    #[test]
+    fn test_symbolize() {
+        let symbols = vec![
+            symbol("_start", 0x1000, 0),
+            symbol("main", 0x1010, 0x10),
+            symbol("loop", 0x1030, 0),
+        ];
+        let bin = Binary::fixture(0x1000..0x1100, symbols, Default::default());
+        let name = |addr| {
+            bin.symbolize(addr)
+                .map(|(sym, offset)| (sym.name.as_str(), offset))
+        };
+        assert_eq!(name(0x1008), Some(("_start", 8)));
+        assert_eq!(name(0x1014), Some(("main", 4)));
+        assert_eq!(name(0x1024), None);
+        assert_eq!(name(0x1034), Some(("loop", 4)));
+        assert_eq!(name(0x1200), None);
+        assert_eq!(name(0xfff), None);
     }
 }